@@ -1,55 +1,295 @@
-use crate::config::Config;
+use crate::config::{
+    Config, ErrorClass, ErrorKind, ErrorPattern, OutputFormat, ProviderConfig, RetryPolicy,
+};
 use crate::logger::log_cli_debug;
 use crate::models::ModelAlias;
+use crate::outcome::{parse_output, ConsultOutcome};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 use thiserror::Error;
-use tokio::process::Command;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tokio::process::{Child, ChildStdin, Command};
+use tokio::sync::watch;
+use tokio_util::sync::CancellationToken;
+
+/// How long a terminated CLI gets to exit on SIGTERM before it is SIGKILLed.
+const TERMINATE_GRACE_PERIOD: Duration = Duration::from_secs(3);
 
 #[derive(Debug, Error)]
 pub enum CliError {
     #[error("Failed to spawn {cli} CLI. Is it installed and in PATH? Error: {source}")]
     SpawnFailed {
-        cli: &'static str,
+        cli: String,
         source: std::io::Error,
     },
     #[error("{cli} CLI exited with code {code}. Error: {stderr}")]
     NonZeroExit {
-        cli: &'static str,
+        cli: String,
         code: i32,
         stderr: String,
     },
-    #[error("Gemini quota exceeded. Consider using gemini-2.0-flash model. Error: {0}")]
-    GeminiQuotaExhausted(String),
+    #[error("{cli} CLI failed: {message}. Error: {stderr}")]
+    ProviderError {
+        cli: String,
+        message: String,
+        stderr: String,
+    },
+    #[error("{cli} CLI is not authenticated. {remediation} Error: {stderr}")]
+    AuthRequired {
+        cli: String,
+        remediation: String,
+        stderr: String,
+    },
+    #[error("{cli} CLI was rate limited{}. {remediation} Error: {stderr}", retry_hint(.retry_after))]
+    RateLimited {
+        cli: String,
+        retry_after: Option<Duration>,
+        remediation: String,
+        stderr: String,
+    },
+    #[error("{cli} quota exceeded. {remediation} Error: {stderr}")]
+    QuotaExhausted {
+        cli: String,
+        remediation: String,
+        stderr: String,
+    },
+    #[error("{cli} CLI does not know model {model}. {remediation} Error: {stderr}")]
+    ModelNotFound {
+        cli: String,
+        model: String,
+        remediation: String,
+        stderr: String,
+    },
+    #[error("Prompt is too long for {cli} CLI. {remediation} Error: {stderr}")]
+    ContextTooLong {
+        cli: String,
+        remediation: String,
+        stderr: String,
+    },
+    #[error("Unknown model alias: {0}")]
+    UnknownAlias(String),
     #[error("No response from {0} CLI (empty stdout)")]
-    EmptyResponse(&'static str),
+    EmptyResponse(String),
+    #[error(
+        "{cli} CLI timed out after {:.1}s and was killed. Partial stdout: {stdout} Partial stderr: {stderr}",
+        .elapsed.as_secs_f64()
+    )]
+    Timeout {
+        cli: String,
+        elapsed: Duration,
+        stdout: String,
+        stderr: String,
+    },
+    #[error("Failed to write prompt file for {cli} CLI: {source}")]
+    PromptFile {
+        cli: String,
+        source: std::io::Error,
+    },
+    #[error("{cli} CLI was cancelled by the client after {:.1}s", .elapsed.as_secs_f64())]
+    Cancelled {
+        cli: String,
+        elapsed: Duration,
+    },
+    #[error(
+        "{cli} not started: {depth} consults are already waiting for a free slot. Try again later or raise maxConcurrent / maxQueueDepth in config.json."
+    )]
+    QueueFull { cli: String, depth: usize },
+    #[error("{cli} not started: the time budget ran out while it waited for a free slot")]
+    BudgetExhausted { cli: String },
+}
+
+impl CliError {
+    /// Class a retry policy matches this error against, if it may be retried
+    /// at all. Bad aliases, prompt-file errors and cancellation never are.
+    pub fn class(&self) -> Option<ErrorClass> {
+        match self {
+            CliError::SpawnFailed { .. } => Some(ErrorClass::SpawnFailed),
+            CliError::NonZeroExit { .. } => Some(ErrorClass::NonZeroExit),
+            CliError::ProviderError { .. } => Some(ErrorClass::ProviderError),
+            CliError::AuthRequired { .. } => Some(ErrorClass::AuthRequired),
+            CliError::RateLimited { .. } => Some(ErrorClass::RateLimited),
+            CliError::QuotaExhausted { .. } => Some(ErrorClass::QuotaExhausted),
+            CliError::ModelNotFound { .. } => Some(ErrorClass::ModelNotFound),
+            CliError::ContextTooLong { .. } => Some(ErrorClass::ContextTooLong),
+            CliError::EmptyResponse(_) => Some(ErrorClass::EmptyResponse),
+            CliError::Timeout { .. } => Some(ErrorClass::Timeout),
+            CliError::UnknownAlias(_)
+            | CliError::PromptFile { .. }
+            | CliError::Cancelled { .. }
+            | CliError::QueueFull { .. }
+            | CliError::BudgetExhausted { .. } => None,
+        }
+    }
+
+    /// Why a fallback provider should be tried instead, if this failure means
+    /// the provider is unavailable rather than that the request was bad.
+    pub fn fallback_reason(&self) -> Option<&'static str> {
+        match self {
+            CliError::QuotaExhausted { .. } => Some("quota exhausted"),
+            CliError::RateLimited { .. } => Some("rate limited"),
+            CliError::ModelNotFound { .. } => Some("model not found"),
+            CliError::AuthRequired { .. } => Some("not authenticated"),
+            CliError::SpawnFailed { .. } => Some("failed to start"),
+            CliError::Timeout { .. } => Some("timed out"),
+            CliError::EmptyResponse(_) => Some("empty response"),
+            _ => None,
+        }
+    }
+}
+
+fn retry_hint(retry_after: &Option<Duration>) -> String {
+    match retry_after {
+        Some(d) => format!(" (retry after {}s)", d.as_secs()),
+        None => String::new(),
+    }
 }
 
+/// Snapshot of a running CLI's output, published as it streams in.
+#[derive(Debug, Clone, Default)]
+pub struct CliProgress {
+    /// Total bytes read so far from stdout and stderr.
+    pub bytes_received: usize,
+    /// Most recent non-blank line of output, if any.
+    pub last_line: Option<String>,
+    /// The last few KiB of stdout, for callers that poll a running consult.
+    pub stdout_tail: String,
+}
+
+/// How much of stdout `CliProgress::stdout_tail` keeps.
+const STDOUT_TAIL_BYTES: usize = 4096;
+
 struct CliSpec {
-    bin: &'static str,
+    bin: String,
     args: Vec<String>,
-    env_overrides: Vec<(&'static str, EnvAction)>,
+    env_overrides: Vec<(String, EnvAction)>,
+    prompt_via: PromptVia,
+    /// Rules classifying a failed run, first match wins.
+    error_patterns: Vec<ErrorPattern>,
+    /// Data piped to the CLI's stdin; stdin is null when `None`.
+    stdin: Option<String>,
+    output_format: OutputFormat,
+    /// Working directory of the CLI; the server's own when `None`.
+    cwd: Option<PathBuf>,
 }
 
 enum EnvAction {
     Remove,
+    Set(String),
+}
+
+/// How a CLI receives the prompt. Never as a plain argv element: large diffs
+/// overflow ARG_MAX and argv is visible to other local users through `ps`.
+enum PromptVia {
+    /// Piped through stdin, with `flag` appended to argv if the CLI needs to
+    /// be told to read it.
+    Stdin { flag: Option<&'static str> },
+    /// Written to a private temp file whose path is appended to argv after
+    /// `flag`, for CLIs that cannot read the prompt from stdin.
+    TempFile { flag: &'static str },
+    /// User-defined argv template: `{prompt}` and `{prompt_file}` in the args
+    /// are substituted, and stdin is used if neither appears.
+    Template,
+}
+
+/// Whether `alias` can continue one of its own sessions, given its id.
+pub fn supports_resume(alias: &str, config: &Config) -> bool {
+    match config.providers.get(alias) {
+        Some(provider) => !provider.resume_args.is_empty(),
+        None => matches!(
+            alias.parse::<ModelAlias>(),
+            Ok(ModelAlias::Claude | ModelAlias::Codex | ModelAlias::Gemini)
+        ),
+    }
 }
 
+/// Resolve `alias` to the CLI to run. A configured provider takes precedence
+/// over the built-in CLI of the same name.
+/// Failure rules from the config are checked before the CLI's built-in ones.
 fn build_cli_spec(
+    alias: &str,
+    model: &str,
+    config: &Config,
+    session: Option<&str>,
+) -> Result<CliSpec, CliError> {
+    let mut spec = if let Some(provider) = config.providers.get(alias) {
+        provider_cli_spec(provider, model, session)
+    } else {
+        let builtin = alias
+            .parse::<ModelAlias>()
+            .map_err(|()| CliError::UnknownAlias(alias.to_string()))?;
+        let mut spec = builtin_cli_spec(builtin, model, config, session);
+        spec.error_patterns = builtin_error_patterns(builtin);
+        spec
+    };
+    if let Some(rules) = config.error_patterns.get(alias) {
+        spec.error_patterns.splice(0..0, rules.iter().cloned());
+    }
+    Ok(spec)
+}
+
+fn provider_cli_spec(provider: &ProviderConfig, model: &str, session: Option<&str>) -> CliSpec {
+    let mut env_overrides: Vec<(String, EnvAction)> = provider
+        .env_remove
+        .iter()
+        .map(|key| (key.clone(), EnvAction::Remove))
+        .collect();
+    env_overrides.extend(
+        provider
+            .env
+            .iter()
+            .map(|(key, value)| (key.clone(), EnvAction::Set(value.clone()))),
+    );
+    CliSpec {
+        bin: provider.bin.clone(),
+        args: provider
+            .args
+            .iter()
+            .chain(session.map_or(&[][..], |_| &provider.resume_args[..]))
+            .map(|arg| {
+                arg.replace("{model}", model)
+                    .replace("{session_id}", session.unwrap_or_default())
+            })
+            .collect(),
+        env_overrides,
+        prompt_via: PromptVia::Template,
+        stdin: None,
+        error_patterns: provider.error_patterns.clone(),
+        output_format: provider.output_format,
+        cwd: None,
+    }
+}
+
+fn builtin_cli_spec(
     alias: ModelAlias,
     model: &str,
-    full_prompt: &str,
     config: &Config,
+    session: Option<&str>,
 ) -> CliSpec {
+    let structured = config.structured_output;
     match alias {
-        ModelAlias::Gemini => CliSpec {
-            bin: "gemini",
-            args: vec![
-                "-m".into(),
-                model.into(),
-                "-p".into(),
-                full_prompt.into(),
-            ],
-            env_overrides: vec![],
-        },
+        // Gemini runs non-interactively and reads the prompt when stdin is not a TTY
+        ModelAlias::Gemini => {
+            let mut args = vec!["-m".into(), model.into()];
+            let output_format = if structured {
+                args.extend(["-o".into(), "json".into()]);
+                OutputFormat::GeminiJson
+            } else {
+                OutputFormat::Text
+            };
+            if let Some(session) = session {
+                args.extend(["--resume".into(), session.into()]);
+            }
+            CliSpec {
+                bin: "gemini".into(),
+                args,
+                env_overrides: vec![],
+                prompt_via: PromptVia::Stdin { flag: None },
+                stdin: None,
+                error_patterns: vec![],
+                output_format,
+                cwd: None,
+            }
+        }
         ModelAlias::Codex => {
             let mut args = vec![
                 "exec".into(),
@@ -61,58 +301,698 @@ fn build_cli_spec(
                 args.push("-c".into());
                 args.push(format!("model_reasoning_effort=\"{effort}\""));
             }
-            args.push(full_prompt.into());
+            let output_format = if structured {
+                args.push("--json".into());
+                OutputFormat::CodexJson
+            } else {
+                OutputFormat::Text
+            };
+            // `codex exec resume <id> -` continues a session; options stay
+            // before the subcommand
+            if let Some(session) = session {
+                args.extend(["resume".into(), session.into()]);
+            }
             CliSpec {
-                bin: "codex",
+                bin: "codex".into(),
                 args,
                 env_overrides: vec![],
+                // `codex exec -` reads the instructions from stdin
+                prompt_via: PromptVia::Stdin { flag: Some("-") },
+                stdin: None,
+                error_patterns: vec![],
+                output_format,
+                cwd: None,
+            }
+        }
+        ModelAlias::Claude => {
+            let mut args = vec!["--print".into(), "--model".into(), model.into()];
+            let output_format = if structured {
+                args.extend(["--output-format".into(), "json".into()]);
+                OutputFormat::ClaudeJson
+            } else {
+                OutputFormat::Text
+            };
+            if let Some(session) = session {
+                args.extend(["--resume".into(), session.into()]);
+            }
+            CliSpec {
+                bin: "claude".into(),
+                args,
+                // Force subscription auth by removing API key
+                env_overrides: vec![("ANTHROPIC_API_KEY".into(), EnvAction::Remove)],
+                prompt_via: PromptVia::Stdin { flag: None },
+                stdin: None,
+                error_patterns: vec![],
+                output_format,
+                cwd: None,
             }
         }
-        ModelAlias::Claude => CliSpec {
-            bin: "claude",
-            args: vec![
-                "--print".into(),
-                "--model".into(),
-                model.into(),
-                full_prompt.into(),
-            ],
-            // Force subscription auth by removing API key
-            env_overrides: vec![("ANTHROPIC_API_KEY", EnvAction::Remove)],
-        },
         ModelAlias::Kilo => CliSpec {
-            bin: "kilo",
+            bin: "kilo".into(),
             args: vec![
                 "run".into(),
                 "-m".into(),
                 model.into(),
-                full_prompt.into(),
+                "Follow the instructions in the attached prompt file.".into(),
             ],
             env_overrides: vec![],
+            prompt_via: PromptVia::TempFile { flag: "--file" },
+            stdin: None,
+            error_patterns: vec![],
+            output_format: OutputFormat::Text,
+            cwd: None,
+        },
+    }
+}
+
+/// Temp file holding a prompt; removed when dropped.
+struct PromptFile {
+    path: std::path::PathBuf,
+}
+
+impl PromptFile {
+    fn create(contents: &str) -> std::io::Result<Self> {
+        use std::io::Write;
+        use std::sync::atomic::{AtomicU64, Ordering};
+
+        static COUNTER: AtomicU64 = AtomicU64::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "grey-rso-prompt-{}-{}.md",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let mut file = options.open(&path)?;
+        // Guard first so a failed write still removes the file
+        let guard = PromptFile { path };
+        file.write_all(contents.as_bytes())?;
+        Ok(guard)
+    }
+}
+
+impl Drop for PromptFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Hand `full_prompt` to the spec the way its CLI expects. The returned temp
+/// file, if any, must outlive the CLI run.
+fn attach_prompt(spec: &mut CliSpec, full_prompt: &str) -> Result<Option<PromptFile>, CliError> {
+    match spec.prompt_via {
+        PromptVia::Stdin { flag } => {
+            spec.args.extend(flag.map(String::from));
+            spec.stdin = Some(full_prompt.to_string());
+            Ok(None)
+        }
+        PromptVia::TempFile { flag } => {
+            let file = PromptFile::create(full_prompt).map_err(|e| CliError::PromptFile {
+                cli: spec.bin.clone(),
+                source: e,
+            })?;
+            spec.args.push(flag.into());
+            spec.args.push(file.path.to_string_lossy().into_owned());
+            Ok(Some(file))
+        }
+        PromptVia::Template => {
+            let file = if spec.args.iter().any(|a| a.contains("{prompt_file}")) {
+                Some(PromptFile::create(full_prompt).map_err(|e| CliError::PromptFile {
+                    cli: spec.bin.clone(),
+                    source: e,
+                })?)
+            } else {
+                None
+            };
+            let in_argv = spec.args.iter().any(|a| a.contains("{prompt}"));
+            // `{prompt_file}` first, so placeholder text inside the prompt is left alone
+            for arg in &mut spec.args {
+                if let Some(ref file) = file {
+                    *arg = arg.replace("{prompt_file}", &file.path.to_string_lossy());
+                }
+                *arg = arg.replace("{prompt}", full_prompt);
+            }
+            if file.is_none() && !in_argv {
+                spec.stdin = Some(full_prompt.to_string());
+            }
+            Ok(file)
+        }
+    }
+}
+
+/// Read `reader` to EOF, appending each chunk to `buf` as it arrives so the
+/// bytes read so far survive if this future is dropped on timeout.
+async fn drain_into<R: AsyncRead + Unpin>(
+    reader: Option<R>,
+    buf: &mut Vec<u8>,
+    progress: Option<&watch::Sender<CliProgress>>,
+    is_stdout: bool,
+) {
+    let Some(mut reader) = reader else { return };
+    let mut chunk = [0u8; 8192];
+    loop {
+        match reader.read(&mut chunk).await {
+            Ok(0) | Err(_) => break,
+            Ok(n) => {
+                buf.extend_from_slice(&chunk[..n]);
+                if let Some(tx) = progress {
+                    let line = last_meaningful_line(buf);
+                    tx.send_modify(|p| {
+                        p.bytes_received += n;
+                        if line.is_some() {
+                            p.last_line = line;
+                        }
+                        if is_stdout {
+                            let tail = &buf[buf.len().saturating_sub(STDOUT_TAIL_BYTES)..];
+                            p.stdout_tail = String::from_utf8_lossy(tail).into_owned();
+                        }
+                    });
+                }
+            }
+        }
+    }
+}
+
+/// Write `data` to the child's stdin and close it so the CLI sees EOF. Runs
+/// alongside the output readers so a large prompt cannot deadlock on a full
+/// pipe.
+async fn feed_stdin(stdin: Option<ChildStdin>, data: Option<&str>) {
+    let (Some(mut stdin), Some(data)) = (stdin, data) else { return };
+    // A CLI that exits without reading all of its input breaks the pipe;
+    // its exit status reports the real problem.
+    let _ = stdin.write_all(data.as_bytes()).await;
+}
+
+/// Last non-blank line in the tail of `buf`, trimmed and capped in length.
+fn last_meaningful_line(buf: &[u8]) -> Option<String> {
+    const TAIL_BYTES: usize = 4096;
+    const MAX_LINE_CHARS: usize = 200;
+
+    let tail = &buf[buf.len().saturating_sub(TAIL_BYTES)..];
+    String::from_utf8_lossy(tail)
+        .lines()
+        .rev()
+        .map(str::trim)
+        .find(|l| !l.is_empty())
+        .map(|l| l.chars().take(MAX_LINE_CHARS).collect())
+}
+
+/// Send `signal` to the child and every process it spawned. The child is
+/// started as the leader of its own process group, so signalling the group
+/// reaches helpers (node workers, sandboxes) that killing the direct child
+/// would orphan.
+#[cfg(unix)]
+fn signal_process_tree(child: &Child, signal: libc::c_int) {
+    if let Some(pid) = child.id() {
+        // SAFETY: killpg has no memory-safety preconditions; a stale pgid
+        // just yields ESRCH.
+        unsafe {
+            libc::killpg(pid as libc::pid_t, signal);
+        }
+    }
+}
+
+/// Ask the process tree to exit with SIGTERM, then SIGKILL whatever is left
+/// once the child has exited or `TERMINATE_GRACE_PERIOD` has passed.
+async fn terminate_process_tree(child: &mut Child) {
+    #[cfg(unix)]
+    {
+        signal_process_tree(child, libc::SIGTERM);
+        let _ = tokio::time::timeout(TERMINATE_GRACE_PERIOD, child.wait()).await;
+        signal_process_tree(child, libc::SIGKILL);
+    }
+    let _ = child.start_kill();
+    let _ = child.wait().await;
+}
+
+fn rule(kind: ErrorKind, pattern: &str) -> ErrorPattern {
+    ErrorPattern {
+        pattern: Some(pattern.into()),
+        exit_code: None,
+        kind,
+        message: None,
+    }
+}
+
+/// Known failure messages of each built-in CLI.
+fn builtin_error_patterns(alias: ModelAlias) -> Vec<ErrorPattern> {
+    use ErrorKind::*;
+    let table: &[(ErrorKind, &str)] = match alias {
+        ModelAlias::Gemini => &[
+            (QuotaExhausted, "RESOURCE_EXHAUSTED"),
+            (AuthRequired, "UNAUTHENTICATED"),
+            (AuthRequired, "Please set an Auth method"),
+            (AuthRequired, "API key not valid"),
+            (ModelNotFound, "is not found for API version"),
+            (ModelNotFound, "NOT_FOUND"),
+            (ContextTooLong, "exceeds the maximum number of tokens"),
+        ],
+        ModelAlias::Claude => &[
+            (AuthRequired, "Invalid API key"),
+            (AuthRequired, "/login"),
+            (AuthRequired, "OAuth token has expired"),
+            (AuthRequired, "authentication_error"),
+            (QuotaExhausted, "usage limit reached"),
+            (RateLimited, "rate_limit_error"),
+            (ModelNotFound, "not_found_error"),
+            (ContextTooLong, "Prompt is too long"),
+        ],
+        ModelAlias::Codex => &[
+            (AuthRequired, "Not logged in"),
+            (AuthRequired, "codex login"),
+            (AuthRequired, "401 Unauthorized"),
+            (QuotaExhausted, "insufficient_quota"),
+            (QuotaExhausted, "hit your usage limit"),
+            (ModelNotFound, "model_not_found"),
+            (ContextTooLong, "context_length_exceeded"),
+        ],
+        ModelAlias::Kilo => &[
+            (AuthRequired, "API key is missing"),
+            (AuthRequired, "No API key"),
+            (ModelNotFound, "ProviderModelNotFoundError"),
+            (ModelNotFound, "Model not found"),
+        ],
+    };
+    table.iter().map(|&(kind, pattern)| rule(kind, pattern)).collect()
+}
+
+/// Failure messages common to many CLIs and the APIs behind them. Checked
+/// after the CLI's own rules, and against stderr only: stdout holds the
+/// model's partial answer, which may mention these words in passing.
+fn generic_error_patterns() -> Vec<ErrorPattern> {
+    use ErrorKind::*;
+    [
+        (RateLimited, "rate limit"),
+        (RateLimited, "429 Too Many Requests"),
+        (QuotaExhausted, "quota exceeded"),
+        (AuthRequired, "401 Unauthorized"),
+        (ModelNotFound, "model not found"),
+        (ContextTooLong, "context length"),
+        (ContextTooLong, "context window"),
+        (ContextTooLong, "too many tokens"),
+    ]
+    .iter()
+    .map(|&(kind, pattern)| rule(kind, pattern))
+    .collect()
+}
+
+fn pattern_matches(rule: &ErrorPattern, code: i32, output: &str) -> bool {
+    if rule.pattern.is_none() && rule.exit_code.is_none() {
+        return false;
+    }
+    let code_ok = rule.exit_code.is_none_or(|c| c == code);
+    let text_ok = rule
+        .pattern
+        .as_ref()
+        .is_none_or(|p| output.contains(&p.to_lowercase()));
+    code_ok && text_ok
+}
+
+/// Parse a retry delay such as "retry after 30s", "retryDelay: \"37s\"" or
+/// "try again in 2 minutes" out of CLI output.
+fn parse_retry_after(output: &str) -> Option<Duration> {
+    let lower = output.to_lowercase();
+    let start = ["retry", "try again"]
+        .iter()
+        .filter_map(|needle| lower.find(needle))
+        .min()?;
+    let rest = &lower[start..];
+    let digits_at = rest.find(|c: char| c.is_ascii_digit())?;
+    // The number must follow the keyword closely to belong to it
+    if digits_at > 24 {
+        return None;
+    }
+    let rest = &rest[digits_at..];
+    let end = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+    let value: u64 = rest[..end].parse().ok()?;
+    let unit = rest[end..].trim_start_matches([' ', '"']);
+    let secs = if unit.starts_with("ms") {
+        value / 1000
+    } else if unit.starts_with('m') {
+        value.saturating_mul(60)
+    } else if unit.starts_with('h') {
+        value.saturating_mul(3600)
+    } else {
+        value
+    };
+    Some(Duration::from_secs(secs))
+}
+
+/// Turn a failed run into a typed error using the spec's rules, falling back
+/// to `NonZeroExit` with the raw stderr. The spec's rules see stderr and
+/// stdout, the generic ones stderr alone.
+fn classify_failure(spec: &CliSpec, model: &str, code: i32, stdout: &str, stderr: &str) -> CliError {
+    let cli = spec.bin.clone();
+    let stderr = stderr.trim().to_string();
+    let output = format!("{stderr}\n{stdout}").to_lowercase();
+    let errors = stderr.to_lowercase();
+
+    let generic = generic_error_patterns();
+    let matched = spec
+        .error_patterns
+        .iter()
+        .find(|r| pattern_matches(r, code, &output))
+        .map(|r| (r, &output))
+        .or_else(|| {
+            generic
+                .iter()
+                .find(|r| pattern_matches(r, code, &errors))
+                .map(|r| (r, &errors))
+        });
+    let Some((rule, matched_text)) = matched else {
+        return CliError::NonZeroExit { cli, code, stderr };
+    };
+
+    let remediation = rule.message.clone().unwrap_or_else(|| match rule.kind {
+        ErrorKind::AuthRequired => match cli.as_str() {
+            "claude" => "Run `claude` and log in with /login.".into(),
+            "codex" => "Run `codex login`.".into(),
+            "gemini" => "Run `gemini` interactively to authenticate.".into(),
+            "kilo" => "Run `kilo auth login` to configure a provider key.".into(),
+            _ => format!("Log in to the {cli} CLI or configure its API key."),
+        },
+        ErrorKind::RateLimited => "Wait and retry, or consult a different model.".into(),
+        ErrorKind::QuotaExhausted if cli == "gemini" => {
+            "Consider using gemini-2.0-flash model or a different alias.".into()
+        }
+        ErrorKind::QuotaExhausted => "Use a different model alias until the quota resets.".into(),
+        ErrorKind::ModelNotFound => {
+            "Check the model name under `models` in config.json.".into()
+        }
+        ErrorKind::ContextTooLong => {
+            "Include fewer context files or a narrower git diff.".into()
+        }
+        ErrorKind::Other => format!(
+            "Output matched error pattern `{}`.",
+            rule.pattern.as_deref().unwrap_or_default()
+        ),
+    });
+
+    match rule.kind {
+        ErrorKind::AuthRequired => CliError::AuthRequired {
+            cli,
+            remediation,
+            stderr,
+        },
+        ErrorKind::RateLimited => CliError::RateLimited {
+            cli,
+            retry_after: parse_retry_after(matched_text),
+            remediation,
+            stderr,
+        },
+        ErrorKind::QuotaExhausted => CliError::QuotaExhausted {
+            cli,
+            remediation,
+            stderr,
+        },
+        ErrorKind::ModelNotFound => CliError::ModelNotFound {
+            cli,
+            model: model.to_string(),
+            remediation,
+            stderr,
+        },
+        ErrorKind::ContextTooLong => CliError::ContextTooLong {
+            cli,
+            remediation,
+            stderr,
+        },
+        ErrorKind::Other => CliError::ProviderError {
+            cli,
+            message: remediation,
+            stderr,
         },
     }
 }
 
+/// Delay before retry number `retry` (1-based) under `policy`, honouring a
+/// provider-supplied retry-after hint.
+fn retry_delay(policy: &RetryPolicy, retry: u32, retry_after: Option<Duration>) -> Duration {
+    let max = Duration::from_millis(policy.max_delay_ms);
+    if let Some(hint) = retry_after {
+        return hint.min(max);
+    }
+    let backoff = Duration::from_millis(policy.base_delay_ms)
+        .saturating_mul(2u32.saturating_pow(retry.saturating_sub(1)))
+        .min(max);
+    // Spread in [-jitter, +jitter] of the backoff, from a randomly keyed hasher
+    let random = {
+        use std::hash::{BuildHasher, Hasher};
+        std::collections::hash_map::RandomState::new().build_hasher().finish()
+    };
+    let unit = (random as f64 / u64::MAX as f64) * 2.0 - 1.0;
+    backoff.mul_f64((1.0 + unit * policy.jitter.clamp(0.0, 1.0)).max(0.0))
+}
+
+/// Result of [`execute_cli`], across all attempts.
+#[derive(Debug)]
+pub struct CliRun {
+    pub result: Result<ConsultOutcome, CliError>,
+    pub attempts: u32,
+    /// Exit code of the last attempt, if the CLI exited on its own.
+    pub exit_code: Option<i32>,
+}
+
+/// What to ask which CLI.
+#[derive(Debug, Clone, Copy)]
+pub struct CliRequest<'a> {
+    pub alias: &'a str,
+    pub model: &'a str,
+    pub prompt: &'a str,
+    /// Provider session to continue instead of starting a new one.
+    pub session: Option<&'a str>,
+    /// Directory to run the CLI in; the server's own when `None`.
+    pub workdir: Option<&'a Path>,
+}
+
+/// Run the CLI for `request.alias`, retrying transient failures under the
+/// alias's retry policy. `timeout` bounds all attempts and the backoff
+/// between them together; a retry whose backoff would pass it is not made.
+///
+/// The caller's limiter slot, if any, stays held during backoff so a retry
+/// does not queue behind newer consults.
 pub async fn execute_cli(
-    alias: ModelAlias,
-    model: &str,
-    full_prompt: &str,
+    request: &CliRequest<'_>,
+    config: &Config,
+    timeout: Duration,
+    cancel: &CancellationToken,
+    progress: Option<&watch::Sender<CliProgress>>,
+) -> CliRun {
+    let alias = request.alias;
+    let policy = config.retry_for(alias);
+    let max_attempts = policy.max_attempts.max(1);
+    let start = std::time::Instant::now();
+    let deadline = start + timeout;
+    let mut attempt = 1;
+    loop {
+        if let Some(tx) = progress {
+            tx.send_replace(CliProgress::default());
+        }
+        let remaining = deadline.saturating_duration_since(std::time::Instant::now());
+        let (result, exit_code) = execute_once(request, config, remaining, cancel, progress).await;
+        let err = match result {
+            Ok(outcome) => {
+                return CliRun {
+                    result: Ok(outcome),
+                    attempts: attempt,
+                    exit_code,
+                }
+            }
+            Err(e) => e,
+        };
+        let retryable = err.class().is_some_and(|c| policy.retry_on.contains(&c));
+        if attempt >= max_attempts || !retryable {
+            if attempt > 1 {
+                log_cli_debug(&format!(
+                    "Attempt {attempt}/{max_attempts} for {alias} failed, giving up: {err}"
+                ));
+            }
+            return CliRun {
+                result: Err(err),
+                attempts: attempt,
+                exit_code,
+            };
+        }
+
+        let retry_after = match err {
+            CliError::RateLimited { retry_after, .. } => retry_after,
+            _ => None,
+        };
+        let delay = retry_delay(policy, attempt, retry_after);
+        if std::time::Instant::now() + delay >= deadline {
+            log_cli_debug(&format!(
+                "Attempt {attempt}/{max_attempts} for {alias} failed, no time left to retry \
+                 in {:.1}s: {err}",
+                delay.as_secs_f64()
+            ));
+            return CliRun {
+                result: Err(err),
+                attempts: attempt,
+                exit_code,
+            };
+        }
+        log_cli_debug(&format!(
+            "Attempt {attempt}/{max_attempts} for {alias} failed, retrying in {:.1}s: {err}",
+            delay.as_secs_f64()
+        ));
+        tokio::select! {
+            _ = tokio::time::sleep(delay) => {}
+            _ = cancel.cancelled() => {
+                // Name the binary, as a cancel during an attempt does
+                let cli = build_cli_spec(alias, request.model, config, request.session)
+                    .map_or_else(|_| alias.to_string(), |spec| spec.bin);
+                return CliRun {
+                    result: Err(CliError::Cancelled {
+                        cli,
+                        elapsed: start.elapsed(),
+                    }),
+                    attempts: attempt,
+                    exit_code: None,
+                };
+            }
+        }
+        attempt += 1;
+    }
+}
+
+/// One attempt: the outcome plus the CLI's exit code, if it got as far as
+/// exiting on its own.
+async fn execute_once(
+    request: &CliRequest<'_>,
+    config: &Config,
+    timeout: Duration,
+    cancel: &CancellationToken,
+    progress: Option<&watch::Sender<CliProgress>>,
+) -> (Result<ConsultOutcome, CliError>, Option<i32>) {
+    match spawn_cli(request, config, timeout, cancel, progress).await {
+        Ok((spec, output)) => (
+            interpret_output(&spec, request.model, &output),
+            output.status.code(),
+        ),
+        Err(e) => (Err(e), None),
+    }
+}
+
+async fn spawn_cli(
+    request: &CliRequest<'_>,
     config: &Config,
-) -> Result<String, CliError> {
-    let spec = build_cli_spec(alias, model, full_prompt, config);
+    timeout: Duration,
+    cancel: &CancellationToken,
+    progress: Option<&watch::Sender<CliProgress>>,
+) -> Result<(CliSpec, CliOutput), CliError> {
+    let mut spec = build_cli_spec(request.alias, request.model, config, request.session)?;
+    spec.cwd = request.workdir.map(Path::to_path_buf);
+    let _prompt_file = attach_prompt(&mut spec, request.prompt)?;
 
     log_cli_debug(&format!(
-        "Spawning {} CLI: alias={}, model={}, prompt_len={}",
+        "Spawning {} CLI: alias={}, model={}, prompt_len={}, timeout={:?}, session={}, cwd={}",
         spec.bin,
-        alias,
-        model,
-        full_prompt.len()
+        request.alias,
+        request.model,
+        request.prompt.len(),
+        timeout,
+        request.session.unwrap_or("new"),
+        request
+            .workdir
+            .map_or_else(|| ".".into(), |dir| dir.display().to_string())
     ));
 
-    let mut cmd = Command::new(spec.bin);
+    let output = run_spec(&spec, timeout, cancel, progress).await?;
+    Ok((spec, output))
+}
+
+/// Turn a finished run into the answer or a classified error.
+fn interpret_output(
+    spec: &CliSpec,
+    model: &str,
+    output: &CliOutput,
+) -> Result<ConsultOutcome, CliError> {
+    if !output.status.success() {
+        let code = output.status.code().unwrap_or(-1);
+        return Err(classify_failure(
+            spec,
+            model,
+            code,
+            &output.stdout,
+            &output.stderr,
+        ));
+    }
+
+    let outcome = match parse_output(spec.output_format, &output.stdout) {
+        Some(Ok(outcome)) => outcome,
+        Some(Err(message)) => {
+            // Reported inside a successful run's output; classify it like a
+            // failed run, keeping the CLI's own message if no rule matches.
+            // The message is an error report, so the generic rules see it too
+            let errors = format!("{message}\n{}", output.stderr);
+            return Err(match classify_failure(spec, model, 0, "", &errors) {
+                CliError::NonZeroExit { cli, .. } => CliError::ProviderError {
+                    cli,
+                    message,
+                    stderr: output.stderr.trim().to_string(),
+                },
+                err => err,
+            });
+        }
+        None => {
+            if spec.output_format != OutputFormat::Text {
+                log_cli_debug(&format!(
+                    "{} output is not in the expected {:?} shape, using it as plain text",
+                    spec.bin, spec.output_format
+                ));
+            }
+            ConsultOutcome::from_text(&output.stdout)
+        }
+    };
+    if outcome.text.is_empty() {
+        return Err(CliError::EmptyResponse(spec.bin.clone()));
+    }
+
+    Ok(outcome)
+}
+
+struct CliOutput {
+    status: std::process::ExitStatus,
+    stdout: String,
+    stderr: String,
+}
+
+enum Stopped {
+    Exited(std::io::Result<std::process::ExitStatus>),
+    TimedOut,
+    Cancelled,
+}
+
+/// Spawn the CLI described by `spec` and collect its output, terminating its
+/// whole process tree if it runs longer than `timeout` or `cancel` fires.
+/// Output is read incrementally and, when `progress` is given, published to
+/// it chunk by chunk.
+async fn run_spec(
+    spec: &CliSpec,
+    timeout: Duration,
+    cancel: &CancellationToken,
+    progress: Option<&watch::Sender<CliProgress>>,
+) -> Result<CliOutput, CliError> {
+    let mut cmd = Command::new(&spec.bin);
     cmd.args(&spec.args);
-    cmd.stdin(std::process::Stdio::null());
+    cmd.stdin(if spec.stdin.is_some() {
+        std::process::Stdio::piped()
+    } else {
+        std::process::Stdio::null()
+    });
     cmd.stdout(std::process::Stdio::piped());
     cmd.stderr(std::process::Stdio::piped());
+    cmd.kill_on_drop(true);
+    #[cfg(unix)]
+    cmd.process_group(0);
+    if let Some(ref dir) = spec.cwd {
+        cmd.current_dir(dir);
+    }
 
     // Apply env overrides
     for (key, action) in &spec.env_overrides {
@@ -120,48 +1000,559 @@ pub async fn execute_cli(
             EnvAction::Remove => {
                 cmd.env_remove(key);
             }
+            EnvAction::Set(value) => {
+                cmd.env(key, value);
+            }
         }
     }
 
     let start = std::time::Instant::now();
 
-    let output = cmd.output().await.map_err(|e| CliError::SpawnFailed {
-        cli: spec.bin,
+    let mut child = cmd.spawn().map_err(|e| CliError::SpawnFailed {
+        cli: spec.bin.clone(),
         source: e,
     })?;
 
+    let stdin_pipe = child.stdin.take();
+    let stdout_pipe = child.stdout.take();
+    let stderr_pipe = child.stderr.take();
+    let mut stdout_buf = Vec::new();
+    let mut stderr_buf = Vec::new();
+
+    let stopped = {
+        let io = async {
+            tokio::join!(
+                feed_stdin(stdin_pipe, spec.stdin.as_deref()),
+                drain_into(stdout_pipe, &mut stdout_buf, progress, true),
+                drain_into(stderr_pipe, &mut stderr_buf, progress, false),
+            );
+            child.wait().await
+        };
+        tokio::select! {
+            status = io => Stopped::Exited(status),
+            _ = tokio::time::sleep(timeout) => Stopped::TimedOut,
+            _ = cancel.cancelled() => Stopped::Cancelled,
+        }
+    };
+
     let duration = start.elapsed();
-    let stdout = String::from_utf8_lossy(&output.stdout).to_string();
-    let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+
+    let status = match stopped {
+        Stopped::Exited(status) => status.map_err(|e| CliError::SpawnFailed {
+            cli: spec.bin.clone(),
+            source: e,
+        })?,
+        Stopped::Cancelled => {
+            terminate_process_tree(&mut child).await;
+            log_cli_debug(&format!("{} CLI cancelled: duration={:?}", spec.bin, duration));
+            return Err(CliError::Cancelled {
+                cli: spec.bin.clone(),
+                elapsed: duration,
+            });
+        }
+        Stopped::TimedOut => {
+            terminate_process_tree(&mut child).await;
+            let stdout = String::from_utf8_lossy(&stdout_buf).trim().to_string();
+            let stderr = String::from_utf8_lossy(&stderr_buf).trim().to_string();
+            log_cli_debug(&format!(
+                "{} CLI timed out: duration={:?}, stdout_len={}, stderr_len={}",
+                spec.bin,
+                duration,
+                stdout.len(),
+                stderr.len()
+            ));
+            return Err(CliError::Timeout {
+                cli: spec.bin.clone(),
+                elapsed: duration,
+                stdout,
+                stderr,
+            });
+        }
+    };
+
+    let stdout = String::from_utf8_lossy(&stdout_buf).to_string();
+    let stderr = String::from_utf8_lossy(&stderr_buf).to_string();
 
     log_cli_debug(&format!(
         "{} CLI finished: code={:?}, duration={:?}, stdout_len={}, stderr_len={}",
         spec.bin,
-        output.status.code(),
+        status.code(),
         duration,
         stdout.len(),
         stderr.len()
     ));
 
-    if !output.status.success() {
-        let code = output.status.code().unwrap_or(-1);
+    Ok(CliOutput {
+        status,
+        stdout,
+        stderr,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sh(script: &str) -> CliSpec {
+        CliSpec {
+            bin: "sh".into(),
+            args: vec!["-c".into(), script.into()],
+            env_overrides: vec![],
+            prompt_via: PromptVia::Stdin { flag: None },
+            stdin: None,
+            error_patterns: vec![],
+            output_format: OutputFormat::Text,
+            cwd: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn runs_in_the_requested_directory() {
+        let dir = std::env::temp_dir().canonicalize().unwrap();
+        let mut spec = sh("pwd");
+        spec.cwd = Some(dir.clone());
+        let output = run_spec(&spec, Duration::from_secs(10), &CancellationToken::new(), None)
+            .await
+            .unwrap();
+        assert_eq!(Path::new(output.stdout.trim()).canonicalize().unwrap(), dir);
+    }
 
-        // Special case: Gemini quota exhaustion
-        if alias == ModelAlias::Gemini && stderr.contains("RESOURCE_EXHAUSTED") {
-            return Err(CliError::GeminiQuotaExhausted(stderr.trim().to_string()));
+    #[tokio::test]
+    async fn completes_within_timeout() {
+        let cancel = CancellationToken::new();
+        let output = run_spec(&sh("echo hello"), Duration::from_secs(10), &cancel, None)
+            .await
+            .unwrap();
+        assert!(output.status.success());
+        assert_eq!(output.stdout.trim(), "hello");
+    }
+
+    #[tokio::test]
+    async fn timeout_kills_tree_and_keeps_partial_output() {
+        // The backgrounded sleep holds stdout open; only a group kill ends it.
+        let spec = sh("echo partial; echo oops >&2; sleep 30 & sleep 30");
+        let start = std::time::Instant::now();
+        let err = run_spec(&spec, Duration::from_millis(500), &CancellationToken::new(), None)
+            .await
+            .err()
+            .unwrap();
+        assert!(start.elapsed() < Duration::from_secs(10));
+        match err {
+            CliError::Timeout { stdout, stderr, .. } => {
+                assert_eq!(stdout, "partial");
+                assert_eq!(stderr, "oops");
+            }
+            other => panic!("expected timeout, got {other}"),
         }
+    }
 
-        return Err(CliError::NonZeroExit {
-            cli: spec.bin,
-            code,
-            stderr: stderr.trim().to_string(),
+    #[tokio::test]
+    async fn cancellation_terminates_cli() {
+        let cancel = CancellationToken::new();
+        let trigger = cancel.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            trigger.cancel();
+        });
+        let start = std::time::Instant::now();
+        let err = run_spec(&sh("sleep 30"), Duration::from_secs(60), &cancel, None)
+            .await
+            .err()
+            .unwrap();
+        assert!(start.elapsed() < Duration::from_secs(10));
+        assert!(matches!(err, CliError::Cancelled { .. }), "got {err}");
+    }
+
+    #[tokio::test]
+    async fn progress_tracks_bytes_and_last_line() {
+        let (tx, rx) = watch::channel(CliProgress::default());
+        let spec = sh("printf 'first\\nsecond\\n\\n'");
+        run_spec(&spec, Duration::from_secs(10), &CancellationToken::new(), Some(&tx))
+            .await
+            .unwrap();
+        let progress = rx.borrow().clone();
+        assert_eq!(progress.bytes_received, 14);
+        assert_eq!(progress.last_line.as_deref(), Some("second"));
+    }
+
+    #[tokio::test]
+    async fn prompt_is_piped_through_stdin() {
+        let mut spec = sh("cat");
+        let prompt = "x".repeat(1 << 20);
+        assert!(attach_prompt(&mut spec, &prompt).unwrap().is_none());
+        assert_eq!(spec.args, ["-c", "cat"]);
+        let output = run_spec(&spec, Duration::from_secs(10), &CancellationToken::new(), None)
+            .await
+            .unwrap();
+        assert_eq!(output.stdout, prompt);
+    }
+
+    #[test]
+    fn temp_file_fallback_is_removed_after_use() {
+        let mut spec = sh("cat");
+        spec.prompt_via = PromptVia::TempFile { flag: "--file" };
+        let file = attach_prompt(&mut spec, "hello").unwrap().unwrap();
+        assert!(spec.stdin.is_none());
+        assert_eq!(spec.args[2], "--file");
+        let path = std::path::PathBuf::from(&spec.args[3]);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "hello");
+        drop(file);
+        assert!(!path.exists());
+    }
+
+    fn provider(args: &[&str]) -> ProviderConfig {
+        ProviderConfig {
+            bin: "llm".into(),
+            args: args.iter().map(|a| a.to_string()).collect(),
+            env_remove: vec![],
+            env: Default::default(),
+            error_patterns: vec![],
+            output_format: OutputFormat::Text,
+            resume_args: vec![],
+        }
+    }
+
+    #[test]
+    fn template_substitutes_model_and_prompt() {
+        let mut spec = provider_cli_spec(&provider(&["-m", "{model}", "{prompt}"]), "mini", None);
+        assert!(attach_prompt(&mut spec, "why {prompt_file}?").unwrap().is_none());
+        assert_eq!(spec.args, ["-m", "mini", "why {prompt_file}?"]);
+        assert!(spec.stdin.is_none());
+    }
+
+    #[test]
+    fn template_writes_prompt_file() {
+        let mut spec = provider_cli_spec(&provider(&["--input={prompt_file}"]), "mini", None);
+        let file = attach_prompt(&mut spec, "hello").unwrap().unwrap();
+        let path = spec.args[0].strip_prefix("--input=").unwrap();
+        assert_eq!(std::fs::read_to_string(path).unwrap(), "hello");
+        drop(file);
+    }
+
+    #[test]
+    fn template_without_placeholder_uses_stdin() {
+        let mut spec = provider_cli_spec(&provider(&["chat"]), "mini", None);
+        assert!(attach_prompt(&mut spec, "hello").unwrap().is_none());
+        assert_eq!(spec.args, ["chat"]);
+        assert_eq!(spec.stdin.as_deref(), Some("hello"));
+    }
+
+    #[tokio::test]
+    async fn error_pattern_maps_failure() {
+        let mut p = provider(&["-c", "echo 'No key found for openai' >&2; exit 1"]);
+        p.bin = "sh".into();
+        p.error_patterns.push(ErrorPattern {
+            pattern: Some("No key found".into()),
+            exit_code: Some(1),
+            kind: ErrorKind::Other,
+            message: Some("Run `llm keys set openai`".into()),
+        });
+        let mut config = Config::default();
+        config.providers.insert("llm".into(), p);
+        let run = execute_cli(
+            &CliRequest {
+                alias: "llm",
+                model: "mini",
+                prompt: "hi",
+                session: None,
+                workdir: None,
+            },
+            &config,
+            Duration::from_secs(10),
+            &CancellationToken::new(),
+            None,
+        )
+        .await;
+        assert_eq!(run.attempts, 1);
+        assert_eq!(run.exit_code, Some(1));
+        let err = run.result.err().unwrap();
+        assert!(
+            matches!(err, CliError::ProviderError { ref message, .. } if message.contains("llm keys set")),
+            "got {err}"
+        );
+    }
+
+    fn classify(alias: &str, code: i32, stderr: &str) -> CliError {
+        let spec = build_cli_spec(alias, "some-model", &Config::default(), None).unwrap();
+        classify_failure(&spec, "some-model", code, "", stderr)
+    }
+
+    #[test]
+    fn classifies_builtin_failures() {
+        assert!(matches!(
+            classify("gemini", 1, "Error: RESOURCE_EXHAUSTED"),
+            CliError::QuotaExhausted { .. }
+        ));
+        assert!(matches!(
+            classify("claude", 1, "OAuth token has expired. Please run /login"),
+            CliError::AuthRequired { .. }
+        ));
+        assert!(matches!(
+            classify("codex", 1, "stream error: model_not_found"),
+            CliError::ModelNotFound { .. }
+        ));
+        assert!(matches!(
+            classify("claude", 1, "Prompt is too long"),
+            CliError::ContextTooLong { .. }
+        ));
+        assert!(matches!(
+            classify("kilo", 2, "segfault"),
+            CliError::NonZeroExit { code: 2, .. }
+        ));
+    }
+
+    #[test]
+    fn structured_output_is_opt_in() {
+        let mut config = Config::default();
+        for alias in ["claude", "gemini", "codex"] {
+            let spec = build_cli_spec(alias, "m", &config, None).unwrap();
+            assert!(!spec.args.iter().any(|a| a.contains("json")), "{alias}: {:?}", spec.args);
+            assert_eq!(spec.output_format, OutputFormat::Text);
+        }
+        config.structured_output = true;
+        for alias in ["claude", "gemini", "codex"] {
+            let spec = build_cli_spec(alias, "m", &config, None).unwrap();
+            assert!(spec.args.iter().any(|a| a.contains("json")), "{alias}: {:?}", spec.args);
+        }
+    }
+
+    #[test]
+    fn generic_rules_ignore_stdout() {
+        let spec = build_cli_spec("kilo", "m", &Config::default(), None).unwrap();
+        let stdout = "The rate limit module does not exist yet; requests are Unauthorized \
+                      until retry after 30s.";
+        assert!(matches!(
+            classify_failure(&spec, "m", 1, stdout, "crashed"),
+            CliError::NonZeroExit { code: 1, .. }
+        ));
+        match classify_failure(&spec, "m", 1, stdout, "Error: rate limit hit") {
+            CliError::RateLimited { retry_after, .. } => assert_eq!(retry_after, None),
+            other => panic!("expected rate limit, got {other}"),
+        }
+    }
+
+    #[test]
+    fn rate_limit_carries_retry_after() {
+        match classify("codex", 1, "429 Too Many Requests: please try again in 20s") {
+            CliError::RateLimited { retry_after, .. } => {
+                assert_eq!(retry_after, Some(Duration::from_secs(20)));
+            }
+            other => panic!("expected rate limit, got {other}"),
+        }
+        assert_eq!(parse_retry_after("retryDelay: \"37s\""), Some(Duration::from_secs(37)));
+        assert_eq!(parse_retry_after("retry after 2 minutes"), Some(Duration::from_secs(120)));
+        assert_eq!(parse_retry_after("rate limit hit"), None);
+        let huge = parse_retry_after(&format!("retry in {} hours", u64::MAX));
+        assert_eq!(huge, Some(Duration::from_secs(u64::MAX)));
+    }
+
+    #[test]
+    fn configured_rules_take_precedence() {
+        let mut config = Config::default();
+        config.error_patterns.insert(
+            "gemini".into(),
+            vec![ErrorPattern {
+                pattern: None,
+                exit_code: Some(41),
+                kind: ErrorKind::AuthRequired,
+                message: Some("Set GEMINI_API_KEY.".into()),
+            }],
+        );
+        let spec = build_cli_spec("gemini", "m", &config, None).unwrap();
+        match classify_failure(&spec, "m", 41, "", "RESOURCE_EXHAUSTED") {
+            CliError::AuthRequired { remediation, .. } => {
+                assert_eq!(remediation, "Set GEMINI_API_KEY.");
+            }
+            other => panic!("expected auth error, got {other}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn retries_transient_failures() {
+        // Fails until the marker file exists, which the first attempt creates
+        let marker = std::env::temp_dir().join(format!("grey-rso-retry-{}", std::process::id()));
+        let _ = std::fs::remove_file(&marker);
+        let script = format!(
+            "cat >/dev/null; if [ -e {0} ]; then echo ok; else touch {0}; exit 1; fi",
+            marker.display()
+        );
+        let mut config = Config::default();
+        config
+            .providers
+            .insert("flaky".into(), provider(&["-c", &script]));
+        config.providers.get_mut("flaky").unwrap().bin = "sh".into();
+        config.retry.insert(
+            "flaky".into(),
+            RetryPolicy {
+                max_attempts: 3,
+                base_delay_ms: 10,
+                ..RetryPolicy::default()
+            },
+        );
+        let run = execute_cli(
+            &CliRequest {
+                alias: "flaky",
+                model: "m",
+                prompt: "hi",
+                session: None,
+                workdir: None,
+            },
+            &config,
+            Duration::from_secs(10),
+            &CancellationToken::new(),
+            None,
+        )
+        .await;
+        let _ = std::fs::remove_file(&marker);
+        assert_eq!(run.result.unwrap().text, "ok");
+        assert_eq!(run.attempts, 2);
+    }
+
+    /// Run a provider that executes `script`, retried with `base_delay_ms`
+    /// backoff up to ten times within `timeout`.
+    async fn run_retried(
+        script: &str,
+        base_delay_ms: u64,
+        timeout: Duration,
+        cancel: &CancellationToken,
+    ) -> CliRun {
+        let mut config = Config::default();
+        let mut failing = provider(&["-c", script]);
+        failing.bin = "sh".into();
+        config.providers.insert("failing".into(), failing);
+        config.retry.insert(
+            "failing".into(),
+            RetryPolicy {
+                max_attempts: 10,
+                base_delay_ms,
+                max_delay_ms: base_delay_ms,
+                jitter: 0.0,
+                ..RetryPolicy::default()
+            },
+        );
+        let request = CliRequest {
+            alias: "failing",
+            model: "m",
+            prompt: "hi",
+            session: None,
+            workdir: None,
+        };
+        execute_cli(&request, &config, timeout, cancel, None).await
+    }
+
+    #[tokio::test]
+    async fn retries_share_one_timeout() {
+        let start = std::time::Instant::now();
+        let run = run_retried(
+            "cat >/dev/null; sleep 0.3; exit 1",
+            10,
+            Duration::from_secs(1),
+            &CancellationToken::new(),
+        )
+        .await;
+        assert!(start.elapsed() < Duration::from_millis(1800), "{:?}", start.elapsed());
+        assert!((2..10).contains(&run.attempts), "attempts={}", run.attempts);
+        assert!(run.result.is_err());
+    }
+
+    #[tokio::test]
+    async fn backoff_past_the_timeout_is_not_waited_out() {
+        let start = std::time::Instant::now();
+        let run = run_retried(
+            "cat >/dev/null; exit 1",
+            5_000,
+            Duration::from_secs(1),
+            &CancellationToken::new(),
+        )
+        .await;
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert_eq!(run.attempts, 1);
+        assert!(matches!(run.result, Err(CliError::NonZeroExit { .. })));
+    }
+
+    #[tokio::test]
+    async fn cancel_during_backoff_reports_elapsed_time() {
+        let cancel = CancellationToken::new();
+        let trigger = cancel.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(300)).await;
+            trigger.cancel();
         });
+        let run = run_retried("cat >/dev/null; exit 1", 5_000, Duration::from_secs(60), &cancel)
+            .await;
+        match run.result {
+            Err(CliError::Cancelled { cli, elapsed }) => {
+                assert_eq!(cli, "sh");
+                assert!(elapsed >= Duration::from_millis(250), "{elapsed:?}");
+            }
+            other => panic!("expected cancellation, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn structured_output_is_parsed_with_raw_fallback() {
+        let mut config = Config::default();
+        let json = r#"{"result":"parsed","is_error":false,"usage":{"output_tokens":7}}"#;
+        let mut structured = provider(&["-c", &format!("cat >/dev/null; echo '{json}'")]);
+        structured.bin = "sh".into();
+        structured.output_format = OutputFormat::ClaudeJson;
+        config.providers.insert("structured".into(), structured);
+        let mut plain = provider(&["-c", "cat >/dev/null; echo plain answer"]);
+        plain.bin = "sh".into();
+        plain.output_format = OutputFormat::ClaudeJson;
+        config.providers.insert("plain".into(), plain);
+
+        let run = |alias: &'static str| {
+            let config = config.clone();
+            async move {
+                execute_cli(
+                    &CliRequest {
+                        alias,
+                        model: "m",
+                        prompt: "hi",
+                        session: None,
+                        workdir: None,
+                    },
+                    &config,
+                    Duration::from_secs(10),
+                    &CancellationToken::new(),
+                    None,
+                )
+                .await
+                .result
+                .unwrap()
+            }
+        };
+        let outcome = run("structured").await;
+        assert_eq!(outcome.text, "parsed");
+        assert_eq!(outcome.output_tokens, Some(7));
+        assert_eq!(run("plain").await, ConsultOutcome::from_text("plain answer"));
     }
 
-    let trimmed = stdout.trim().to_string();
-    if trimmed.is_empty() {
-        return Err(CliError::EmptyResponse(spec.bin));
+    #[test]
+    fn retry_delay_backs_off_and_honours_hint() {
+        let policy = RetryPolicy {
+            base_delay_ms: 1_000,
+            max_delay_ms: 5_000,
+            jitter: 0.0,
+            ..RetryPolicy::default()
+        };
+        assert_eq!(retry_delay(&policy, 1, None), Duration::from_secs(1));
+        assert_eq!(retry_delay(&policy, 3, None), Duration::from_secs(4));
+        assert_eq!(retry_delay(&policy, 4, None), Duration::from_secs(5));
+        assert_eq!(
+            retry_delay(&policy, 1, Some(Duration::from_secs(3))),
+            Duration::from_secs(3)
+        );
+        assert_eq!(
+            retry_delay(&policy, 1, Some(Duration::from_secs(u64::MAX))),
+            Duration::from_secs(5)
+        );
     }
 
-    Ok(trimmed)
+    #[test]
+    fn last_line_skips_blank_lines() {
+        assert_eq!(last_meaningful_line(b"a\n  b  \n\n  \n").as_deref(), Some("b"));
+        assert_eq!(last_meaningful_line(b"\n\n"), None);
+    }
 }
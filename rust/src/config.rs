@@ -2,29 +2,338 @@ use crate::models::{default_model_mapping, ModelAlias, DEFAULT_ALIAS};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Applied when neither the call nor the config sets a timeout for an alias.
+pub const DEFAULT_TIMEOUT_SECS: u64 = 600;
+
+/// Consultant CLIs allowed to run at once across the whole server.
+pub const DEFAULT_MAX_CONCURRENT: usize = 4;
+
+/// How long finished background consults stay fetchable.
+pub const DEFAULT_JOB_RETENTION_SECS: u64 = 3600;
+
+/// Consults allowed to wait for a free slot before new ones are rejected.
+pub const DEFAULT_MAX_QUEUE_DEPTH: usize = 16;
+
+/// Bytes of file contents inlined into one prompt, across all files.
+pub const DEFAULT_CONTEXT_BUDGET_BYTES: usize = 400_000;
+
+/// Lines shown on each side of a `path:START-END` or `path#symbol` selection.
+pub const DEFAULT_SELECTION_CONTEXT_LINES: usize = 5;
+
+/// Size of the consult history kept on disk, across its two files.
+pub const DEFAULT_HISTORY_MAX_BYTES: u64 = 10_000_000;
+
+/// Files that the directories and globs in one call's `files` may expand to.
+pub const DEFAULT_MAX_EXPANDED_FILES: usize = 100;
+
+/// Total size of the files those directories and globs may expand to.
+pub const DEFAULT_MAX_EXPANDED_BYTES: u64 = 1_000_000;
+
+/// A user-defined consultant CLI, declared under `providers` in config.json.
+///
+/// Each `args` element may contain `{model}`, `{prompt}` and `{prompt_file}`
+/// placeholders. Without `{prompt}` or `{prompt_file}` the prompt is piped
+/// through stdin.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProviderConfig {
+    pub bin: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// Environment variables removed before spawning the CLI.
+    #[serde(default)]
+    pub env_remove: Vec<String>,
+    /// Environment variables set before spawning the CLI.
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    /// Rules classifying this CLI's failures.
+    #[serde(default)]
+    pub error_patterns: Vec<ErrorPattern>,
+    /// Shape of the CLI's stdout, for wrappers around a built-in CLI's JSON mode.
+    #[serde(default)]
+    pub output_format: OutputFormat,
+    /// Appended to `args` to continue a session; `{session_id}` is replaced
+    /// by the id the CLI reported. Without these, threads are replayed.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub resume_args: Vec<String>,
+}
+
+/// How a CLI's stdout is read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum OutputFormat {
+    /// The answer as plain text.
+    #[default]
+    Text,
+    /// `claude --output-format json`
+    ClaudeJson,
+    /// `gemini -o json`
+    GeminiJson,
+    /// `codex exec --json` event stream
+    CodexJson,
+}
+
+/// How context files are passed to a CLI.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum AttachmentMode {
+    /// `@path` references that the CLI reads itself.
+    Reference,
+    /// The file contents, in fenced blocks under their paths.
+    Inline,
+    /// The contents inline, followed by the references.
+    Both,
+}
+
+/// What to do when context files or the git diff hold a secret.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SecretPolicy {
+    /// Fail the call.
+    Block,
+    /// Replace each secret with a marker before sending.
+    #[default]
+    Redact,
+    /// Send as is and report the findings.
+    Warn,
+}
+
+/// What a recognised CLI failure means.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ErrorKind {
+    AuthRequired,
+    RateLimited,
+    QuotaExhausted,
+    ModelNotFound,
+    ContextTooLong,
+    #[default]
+    Other,
+}
+
+/// Rule classifying a failed CLI run. Every condition given must match.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ErrorPattern {
+    /// Case-insensitive substring of the CLI's stderr or stdout.
+    #[serde(default)]
+    pub pattern: Option<String>,
+    /// Exit code the CLI must have returned.
+    #[serde(default)]
+    pub exit_code: Option<i32>,
+    #[serde(default)]
+    pub kind: ErrorKind,
+    /// Remediation shown to the caller; defaults to one suited to `kind`.
+    #[serde(default)]
+    pub message: Option<String>,
+}
+
+/// Failure classes a retry policy can opt into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ErrorClass {
+    SpawnFailed,
+    Timeout,
+    EmptyResponse,
+    /// A non-zero exit that no error pattern recognised.
+    NonZeroExit,
+    RateLimited,
+    QuotaExhausted,
+    AuthRequired,
+    ModelNotFound,
+    ContextTooLong,
+    ProviderError,
+}
+
+/// How to retry transient CLI failures. One attempt means no retries.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    /// Delay before the first retry; doubled for each further retry.
+    pub base_delay_ms: u64,
+    pub max_delay_ms: u64,
+    /// Random spread applied to each delay, as a fraction of it (0.0–1.0).
+    pub jitter: f64,
+    pub retry_on: Vec<ErrorClass>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 1,
+            base_delay_ms: 2_000,
+            max_delay_ms: 30_000,
+            jitter: 0.2,
+            retry_on: vec![
+                ErrorClass::NonZeroExit,
+                ErrorClass::EmptyResponse,
+                ErrorClass::RateLimited,
+            ],
+        }
+    }
+}
 
 /// On-disk JSON shape — uses string keys for the models map so the config file
 /// stays human-readable (`"gemini": "gemini-3-pro-preview"` etc.).
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Default, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 struct RawConfig {
     #[serde(default)]
-    models: HashMap<ModelAlias, String>,
+    models: HashMap<String, String>,
     #[serde(default)]
-    default_alias: Option<ModelAlias>,
+    default_alias: Option<String>,
     #[serde(default)]
     codex_reasoning_effort: Option<String>,
     #[serde(default)]
     system_prompt_path: Option<String>,
+    #[serde(default)]
+    default_timeout_secs: Option<u64>,
+    /// Per-alias overrides of `default_timeout_secs`.
+    #[serde(default)]
+    timeouts: HashMap<String, u64>,
+    /// User-defined CLIs, keyed by alias. May shadow a built-in alias.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    providers: HashMap<String, ProviderConfig>,
+    /// Aliases to try in order when an alias is unavailable.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    fallbacks: HashMap<String, Vec<String>>,
+    /// Extra failure classification rules per alias, checked before the
+    /// built-in ones.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    error_patterns: HashMap<String, Vec<ErrorPattern>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    default_retry: Option<RetryPolicy>,
+    /// Per-alias overrides of `default_retry`.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    retry: HashMap<String, RetryPolicy>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    max_concurrent: Option<usize>,
+    /// Per-alias caps, applied on top of `max_concurrent`.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    max_concurrent_per_alias: HashMap<String, usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    max_queue_depth: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    job_retention_secs: Option<u64>,
+    /// Ask the built-in CLIs for JSON output carrying usage metadata.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    structured_output: Option<bool>,
+    /// Record consults in the history store under the state directory.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    history: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    history_max_bytes: Option<u64>,
+    /// Per-alias overrides of how context files are passed.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    attachments: HashMap<String, AttachmentMode>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    context_budget_bytes: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    selection_context_lines: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    max_expanded_files: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    max_expanded_bytes: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    secret_policy: Option<SecretPolicy>,
 }
 
 #[derive(Debug, Clone)]
 pub struct Config {
-    pub models: HashMap<ModelAlias, String>,
-    pub default_alias: ModelAlias,
+    pub models: HashMap<String, String>,
+    pub default_alias: String,
     pub codex_reasoning_effort: Option<String>,
     pub system_prompt_path: PathBuf,
+    /// Directory of `<mode>.md` system prompts.
+    pub prompts_dir: PathBuf,
+    pub default_timeout: Duration,
+    pub timeouts: HashMap<String, Duration>,
+    pub providers: HashMap<String, ProviderConfig>,
+    pub fallbacks: HashMap<String, Vec<String>>,
+    pub error_patterns: HashMap<String, Vec<ErrorPattern>>,
+    pub default_retry: RetryPolicy,
+    pub retry: HashMap<String, RetryPolicy>,
+    pub max_concurrent: usize,
+    pub max_concurrent_per_alias: HashMap<String, usize>,
+    pub max_queue_depth: usize,
+    pub job_retention: Duration,
+    pub structured_output: bool,
+    pub history: bool,
+    pub history_max_bytes: u64,
+    pub attachments: HashMap<String, AttachmentMode>,
+    pub context_budget_bytes: usize,
+    pub selection_context_lines: usize,
+    pub max_expanded_files: usize,
+    pub max_expanded_bytes: u64,
+    pub secret_policy: SecretPolicy,
+}
+
+impl Config {
+    /// Timeout for a consult on `alias`, unless the call overrides it.
+    pub fn timeout_for(&self, alias: &str) -> Duration {
+        self.timeouts
+            .get(alias)
+            .copied()
+            .unwrap_or(self.default_timeout)
+    }
+
+    /// Retry policy for consults on `alias`.
+    pub fn retry_for(&self, alias: &str) -> &RetryPolicy {
+        self.retry.get(alias).unwrap_or(&self.default_retry)
+    }
+
+    /// How context files are passed to `alias`. codex exec does not expand
+    /// `@path` references, so built-in codex gets the contents inline.
+    pub fn attachments_for(&self, alias: &str) -> AttachmentMode {
+        if let Some(mode) = self.attachments.get(alias) {
+            return *mode;
+        }
+        match alias.parse() {
+            Ok(ModelAlias::Codex) if !self.providers.contains_key(alias) => AttachmentMode::Inline,
+            _ => AttachmentMode::Reference,
+        }
+    }
+
+    /// Model name passed to the CLI for `alias`; the alias itself if unmapped.
+    pub fn model_for(&self, alias: &str) -> String {
+        self.models
+            .get(alias)
+            .cloned()
+            .unwrap_or_else(|| alias.to_string())
+    }
+
+    /// Every alias a caller may pick: the built-ins plus configured providers,
+    /// sorted.
+    pub fn aliases(&self) -> Vec<String> {
+        let mut aliases: Vec<String> = ModelAlias::ALL.iter().map(|a| a.to_string()).collect();
+        for name in self.providers.keys() {
+            if !aliases.contains(name) {
+                aliases.push(name.clone());
+            }
+        }
+        aliases.sort();
+        aliases
+    }
+
+    pub fn is_known_alias(&self, alias: &str) -> bool {
+        self.providers.contains_key(alias) || alias.parse::<ModelAlias>().is_ok()
+    }
+
+    /// `alias` followed by its configured fallbacks, skipping unknown and
+    /// repeated aliases.
+    pub fn fallback_chain(&self, alias: &str) -> Vec<String> {
+        let mut chain = vec![alias.to_string()];
+        for next in self.fallbacks.get(alias).into_iter().flatten() {
+            if self.is_known_alias(next) && !chain.contains(next) {
+                chain.push(next.clone());
+            }
+        }
+        chain
+    }
 }
 
 pub fn config_dir() -> PathBuf {
@@ -39,12 +348,35 @@ fn config_path() -> PathBuf {
 }
 
 fn write_default_config(path: &PathBuf) {
-    let defaults = default_model_mapping();
+    let defaults = default_model_mapping()
+        .into_iter()
+        .map(|(alias, model)| (alias.to_string(), model))
+        .collect();
     let raw = RawConfig {
         models: defaults,
-        default_alias: Some(DEFAULT_ALIAS),
+        default_alias: Some(DEFAULT_ALIAS.to_string()),
         codex_reasoning_effort: None,
         system_prompt_path: None,
+        default_timeout_secs: Some(DEFAULT_TIMEOUT_SECS),
+        timeouts: HashMap::new(),
+        providers: HashMap::new(),
+        fallbacks: HashMap::new(),
+        error_patterns: HashMap::new(),
+        default_retry: None,
+        retry: HashMap::new(),
+        max_concurrent: Some(DEFAULT_MAX_CONCURRENT),
+        max_concurrent_per_alias: HashMap::new(),
+        max_queue_depth: Some(DEFAULT_MAX_QUEUE_DEPTH),
+        job_retention_secs: Some(DEFAULT_JOB_RETENTION_SECS),
+        structured_output: None,
+        history: None,
+        history_max_bytes: None,
+        attachments: HashMap::new(),
+        context_budget_bytes: None,
+        selection_context_lines: None,
+        max_expanded_files: None,
+        max_expanded_bytes: None,
+        secret_policy: None,
     };
     if let Some(parent) = path.parent() {
         let _ = fs::create_dir_all(parent);
@@ -54,51 +386,96 @@ fn write_default_config(path: &PathBuf) {
 }
 
 pub fn load_config() -> Config {
-    let path = config_path();
-    let dir = config_dir();
+    load_config_from(&config_path(), &config_dir())
+}
 
+/// Read the config at `path`, writing the defaults there first if there is
+/// no file. A file that cannot be read or parsed is reported and left as it
+/// is, and the server runs on the defaults.
+fn load_config_from(path: &PathBuf, dir: &Path) -> Config {
     if !path.exists() {
-        write_default_config(&path);
-    }
-
-    let raw: RawConfig = match fs::read_to_string(&path) {
-        Ok(contents) => match serde_json::from_str(&contents) {
-            Ok(c) => c,
-            Err(_) => {
-                write_default_config(&path);
-                serde_json::from_str(
-                    &fs::read_to_string(&path).expect("re-read default config"),
-                )
-                .expect("parse default config")
-            }
-        },
-        Err(_) => {
-            write_default_config(&path);
-            serde_json::from_str(
-                &fs::read_to_string(&path).expect("re-read default config"),
-            )
-            .expect("parse default config")
-        }
-    };
+        write_default_config(path);
+    }
+
+    let raw = fs::read_to_string(path)
+        .map_err(|e| e.to_string())
+        .and_then(|contents| serde_json::from_str(&contents).map_err(|e| e.to_string()))
+        .unwrap_or_else(|e| {
+            eprintln!("Ignoring {}: {e}. Using the default config.", path.display());
+            RawConfig::default()
+        });
 
-    // Merge with defaults — ensure every alias has a model
-    let mut models = default_model_mapping();
-    for (alias, model) in raw.models {
-        models.insert(alias, model);
+    Config::from_raw(raw, dir)
+}
+
+impl Default for Config {
+    /// Built-in defaults, as if config.json were empty.
+    fn default() -> Self {
+        Config::from_raw(RawConfig::default(), &config_dir())
     }
+}
 
-    let default_alias = raw.default_alias.unwrap_or(DEFAULT_ALIAS);
+impl Config {
+    fn from_raw(raw: RawConfig, dir: &Path) -> Config {
+        // Merge with defaults — ensure every built-in alias has a model
+        let mut models: HashMap<String, String> = default_model_mapping()
+            .into_iter()
+            .map(|(alias, model)| (alias.to_string(), model))
+            .collect();
+        for (alias, model) in raw.models {
+            models.insert(alias, model);
+        }
 
-    let system_prompt_path = match raw.system_prompt_path {
-        Some(p) => PathBuf::from(p),
-        None => dir.join("SYSTEM_PROMPT.md"),
-    };
+        let default_alias = raw
+            .default_alias
+            .unwrap_or_else(|| DEFAULT_ALIAS.to_string());
+
+        let system_prompt_path = match raw.system_prompt_path {
+            Some(p) => PathBuf::from(p),
+            None => dir.join("SYSTEM_PROMPT.md"),
+        };
 
-    Config {
-        models,
-        default_alias,
-        codex_reasoning_effort: raw.codex_reasoning_effort,
-        system_prompt_path,
+        let default_timeout =
+            Duration::from_secs(raw.default_timeout_secs.unwrap_or(DEFAULT_TIMEOUT_SECS));
+        let timeouts = raw
+            .timeouts
+            .into_iter()
+            .map(|(alias, secs)| (alias, Duration::from_secs(secs)))
+            .collect();
+
+        Config {
+            models,
+            default_alias,
+            codex_reasoning_effort: raw.codex_reasoning_effort,
+            system_prompt_path,
+            prompts_dir: dir.join("prompts"),
+            default_timeout,
+            timeouts,
+            providers: raw.providers,
+            fallbacks: raw.fallbacks,
+            error_patterns: raw.error_patterns,
+            default_retry: raw.default_retry.unwrap_or_default(),
+            retry: raw.retry,
+            max_concurrent: raw.max_concurrent.unwrap_or(DEFAULT_MAX_CONCURRENT).max(1),
+            max_concurrent_per_alias: raw.max_concurrent_per_alias,
+            max_queue_depth: raw.max_queue_depth.unwrap_or(DEFAULT_MAX_QUEUE_DEPTH),
+            job_retention: Duration::from_secs(
+                raw.job_retention_secs.unwrap_or(DEFAULT_JOB_RETENTION_SECS),
+            ),
+            structured_output: raw.structured_output.unwrap_or(false),
+            history: raw.history.unwrap_or(true),
+            history_max_bytes: raw.history_max_bytes.unwrap_or(DEFAULT_HISTORY_MAX_BYTES),
+            attachments: raw.attachments,
+            context_budget_bytes: raw
+                .context_budget_bytes
+                .unwrap_or(DEFAULT_CONTEXT_BUDGET_BYTES),
+            selection_context_lines: raw
+                .selection_context_lines
+                .unwrap_or(DEFAULT_SELECTION_CONTEXT_LINES),
+            max_expanded_files: raw.max_expanded_files.unwrap_or(DEFAULT_MAX_EXPANDED_FILES),
+            max_expanded_bytes: raw.max_expanded_bytes.unwrap_or(DEFAULT_MAX_EXPANDED_BYTES),
+            secret_policy: raw.secret_policy.unwrap_or_default(),
+        }
     }
 }
 
@@ -113,4 +490,83 @@ mod tests {
             assert!(models.contains_key(alias), "missing alias {alias}");
         }
     }
+
+    #[test]
+    fn broken_config_is_left_alone() {
+        let dir = std::env::temp_dir().join(format!("grey-rso-config-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("config.json");
+        let broken = "{\"models\": {\"gemini\": \"gemini-2.5-pro\",}}";
+        fs::write(&path, broken).unwrap();
+
+        let config = load_config_from(&path, &dir);
+        assert_eq!(fs::read_to_string(&path).unwrap(), broken);
+        assert_eq!(config.default_alias, DEFAULT_ALIAS.to_string());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn provider_entries_parse() {
+        let raw: RawConfig = serde_json::from_str(
+            r#"{
+                "models": { "llm": "gpt-4o-mini" },
+                "providers": {
+                    "llm": {
+                        "bin": "llm",
+                        "args": ["-m", "{model}", "{prompt}"],
+                        "envRemove": ["OPENAI_BASE_URL"],
+                        "env": { "LLM_USER_PATH": "/tmp/llm" },
+                        "errorPatterns": [{ "pattern": "No key found", "kind": "authRequired", "message": "Run `llm keys set`" }]
+                    }
+                }
+            }"#,
+        )
+        .unwrap();
+        let provider = &raw.providers["llm"];
+        assert_eq!(provider.bin, "llm");
+        assert_eq!(provider.args, ["-m", "{model}", "{prompt}"]);
+        assert_eq!(provider.env_remove, ["OPENAI_BASE_URL"]);
+        assert_eq!(provider.error_patterns[0].pattern.as_deref(), Some("No key found"));
+        assert_eq!(provider.error_patterns[0].kind, ErrorKind::AuthRequired);
+        assert_eq!(raw.models["llm"], "gpt-4o-mini");
+    }
+
+    #[test]
+    fn retry_policy_fills_unset_fields() {
+        let raw: RawConfig =
+            serde_json::from_str(r#"{ "retry": { "codex": { "maxAttempts": 3, "retryOn": ["timeout"] } } }"#)
+                .unwrap();
+        let config = Config::from_raw(raw, Path::new("/tmp"));
+        let policy = config.retry_for("codex");
+        assert_eq!(policy.max_attempts, 3);
+        assert_eq!(policy.retry_on, [ErrorClass::Timeout]);
+        assert_eq!(policy.base_delay_ms, RetryPolicy::default().base_delay_ms);
+        assert_eq!(config.retry_for("gemini").max_attempts, 1);
+    }
+
+    #[test]
+    fn fallback_chain_skips_unknown_and_repeated_aliases() {
+        let mut config = Config::default();
+        config.fallbacks.insert(
+            "gemini".into(),
+            vec!["codex".into(), "nope".into(), "gemini".into(), "claude".into()],
+        );
+        assert_eq!(config.fallback_chain("gemini"), ["gemini", "codex", "claude"]);
+        assert_eq!(config.fallback_chain("kilo"), ["kilo"]);
+    }
+
+    #[test]
+    fn codex_gets_files_inline_unless_overridden() {
+        let raw: RawConfig =
+            serde_json::from_str(r#"{ "attachments": { "gemini": "both" } }"#).unwrap();
+        let mut config = Config::from_raw(raw, Path::new("/tmp"));
+        assert_eq!(config.attachments_for("codex"), AttachmentMode::Inline);
+        assert_eq!(config.attachments_for("claude"), AttachmentMode::Reference);
+        assert_eq!(config.attachments_for("gemini"), AttachmentMode::Both);
+        config.providers.insert(
+            "codex".into(),
+            serde_json::from_str(r#"{ "bin": "codex" }"#).unwrap(),
+        );
+        assert_eq!(config.attachments_for("codex"), AttachmentMode::Reference);
+    }
 }
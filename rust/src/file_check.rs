@@ -2,7 +2,7 @@ use std::fs;
 use std::path::Path;
 use thiserror::Error;
 
-const MAX_CONTEXT_FILE_BYTES: u64 = 200_000;
+pub const MAX_CONTEXT_FILE_BYTES: u64 = 200_000;
 
 #[derive(Debug, Error)]
 pub enum FileCheckError {
@@ -14,6 +14,10 @@ pub enum FileCheckError {
     Binary(String),
     #[error("Blocked sensitive file: {0}")]
     Sensitive(String),
+    #[error("Invalid line range in {path}: {reason}")]
+    BadRange { path: String, reason: String },
+    #[error("Symbol `{symbol}` not found in {path}")]
+    SymbolNotFound { path: String, symbol: String },
     #[error("IO error reading {path}: {source}")]
     Io {
         path: String,
@@ -21,7 +25,177 @@ pub enum FileCheckError {
     },
 }
 
-fn is_sensitive_path(path: &str) -> bool {
+/// A `files` entry: a path, optionally narrowed to `path:START-END` (1-based,
+/// inclusive) or `path#symbol`. An entry naming an existing file is taken
+/// as a plain path, suffix and all.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileSpec<'a> {
+    pub path: &'a str,
+    pub selection: Option<Selection<'a>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Selection<'a> {
+    Lines(usize, usize),
+    Symbol(&'a str),
+}
+
+impl<'a> FileSpec<'a> {
+    pub fn parse(entry: &'a str) -> FileSpec<'a> {
+        let plain = FileSpec {
+            path: entry,
+            selection: None,
+        };
+        if Path::new(entry).exists() {
+            return plain;
+        }
+        if let Some((path, symbol)) = entry.rsplit_once('#') {
+            if !path.is_empty() && is_identifier(symbol) {
+                return FileSpec {
+                    path,
+                    selection: Some(Selection::Symbol(symbol)),
+                };
+            }
+        }
+        if let Some((path, range)) = entry.rsplit_once(':') {
+            let bounds = range
+                .split_once('-')
+                .and_then(|(start, end)| Some((start.parse().ok()?, end.parse().ok()?)));
+            if let (false, Some((start, end))) = (path.is_empty(), bounds) {
+                return FileSpec {
+                    path,
+                    selection: Some(Selection::Lines(start, end)),
+                };
+            }
+        }
+        plain
+    }
+
+    /// The 1-based, inclusive lines of `content` the entry selects, widened
+    /// by `context` lines on each side; `None` for a whole file.
+    pub fn lines(
+        &self,
+        content: &str,
+        context: usize,
+    ) -> Result<Option<(usize, usize)>, FileCheckError> {
+        let total = content.lines().count();
+        let (start, end) = match self.selection {
+            None => return Ok(None),
+            Some(Selection::Lines(start, end)) => {
+                let reason = if start == 0 {
+                    Some("lines are numbered from 1".to_string())
+                } else if start > end {
+                    Some(format!("{start}-{end} is empty"))
+                } else if start > total {
+                    Some(format!("{start}-{end} starts past the end ({total} lines)"))
+                } else {
+                    None
+                };
+                if let Some(reason) = reason {
+                    return Err(FileCheckError::BadRange {
+                        path: self.path.to_string(),
+                        reason,
+                    });
+                }
+                (start, end.min(total))
+            }
+            Some(Selection::Symbol(symbol)) => {
+                find_symbol(content, symbol).ok_or_else(|| FileCheckError::SymbolNotFound {
+                    path: self.path.to_string(),
+                    symbol: symbol.to_string(),
+                })?
+            }
+        };
+        Ok(Some((
+            start.saturating_sub(context).max(1),
+            (end + context).min(total),
+        )))
+    }
+}
+
+fn is_identifier(s: &str) -> bool {
+    !s.is_empty() && s.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '$')
+}
+
+/// Keywords that introduce a named definition, across common languages.
+const DEFINITION_KEYWORDS: &[&str] = &[
+    "fn", "struct", "enum", "trait", "impl", "mod", "type", "const", "static", "union",
+    "macro_rules", "class", "def", "function", "interface", "func", "let", "var",
+];
+
+/// Lines of the definition of `symbol`, including the doc comments and
+/// attributes above it: a brace-delimited block, an indented Python-style
+/// block, or a single statement.
+fn find_symbol(content: &str, symbol: &str) -> Option<(usize, usize)> {
+    let lines: Vec<&str> = content.lines().collect();
+    let def = lines.iter().position(|line| {
+        let tokens: Vec<&str> = line
+            .split(|c: char| !(c.is_alphanumeric() || c == '_' || c == '$'))
+            .filter(|t| !t.is_empty())
+            .collect();
+        tokens
+            .windows(2)
+            .any(|w| w[1] == symbol && DEFINITION_KEYWORDS.contains(&w[0]))
+    })?;
+
+    let mut start = def;
+    while start > 0 {
+        let above = lines[start - 1].trim_start();
+        let preamble = ["///", "//!", "#[", "@", "/**", "*", "//", "#"]
+            .iter()
+            .any(|p| above.starts_with(p));
+        if !preamble {
+            break;
+        }
+        start -= 1;
+    }
+
+    Some((start + 1, block_end(&lines, def) + 1))
+}
+
+/// Index of the last line of the block whose header is at `def`.
+fn block_end(lines: &[&str], def: usize) -> usize {
+    let indent = |line: &str| line.len() - line.trim_start().len();
+    let mut depth = 0i32;
+    let mut opened = false;
+    for (i, line) in lines.iter().enumerate().skip(def) {
+        for c in line.chars() {
+            match c {
+                '{' => {
+                    depth += 1;
+                    opened = true;
+                }
+                '}' => depth -= 1,
+                _ => {}
+            }
+        }
+        let trimmed = line.trim_end();
+        if opened && depth <= 0 {
+            return i;
+        }
+        if !opened && trimmed.ends_with(';') {
+            return i;
+        }
+        if !opened && trimmed.ends_with(':') {
+            // Indented block: runs until the next line indented no deeper
+            // than the header
+            let mut end = i;
+            for (j, next) in lines.iter().enumerate().skip(i + 1) {
+                if next.trim().is_empty() {
+                    continue;
+                }
+                if indent(next) <= indent(lines[def]) {
+                    break;
+                }
+                end = j;
+            }
+            return end;
+        }
+    }
+    def
+}
+
+pub fn is_sensitive_path(path: &str) -> bool {
     let normalized = path.replace('\\', "/");
     let lower = normalized.to_lowercase();
 
@@ -75,17 +249,19 @@ fn segment_matches(normalized_lower: &str, name: &str) -> bool {
     false
 }
 
-fn is_likely_binary(data: &[u8]) -> bool {
+pub fn is_likely_binary(data: &[u8]) -> bool {
     let check_len = data.len().min(8192);
     data[..check_len].contains(&0)
 }
 
 pub fn validate_context_files(files: &[String]) -> Result<(), FileCheckError> {
-    for file in files {
+    for entry in files {
+        let spec = FileSpec::parse(entry);
+        let file = spec.path;
         let path = Path::new(file);
 
         // Canonicalize for sensitive-path check; fall back to the raw string
-        let display_path = file.clone();
+        let display_path = file.to_string();
 
         if !path.exists() {
             return Err(FileCheckError::NotFound(display_path));
@@ -105,7 +281,8 @@ pub fn validate_context_files(files: &[String]) -> Result<(), FileCheckError> {
             source: e,
         })?;
 
-        if meta.len() > MAX_CONTEXT_FILE_BYTES {
+        // A selection only needs its own lines to fit
+        if meta.len() > MAX_CONTEXT_FILE_BYTES && spec.selection.is_none() {
             return Err(FileCheckError::TooLarge(display_path));
         }
 
@@ -117,6 +294,19 @@ pub fn validate_context_files(files: &[String]) -> Result<(), FileCheckError> {
         if is_likely_binary(&content) {
             return Err(FileCheckError::Binary(display_path));
         }
+
+        let text = String::from_utf8_lossy(&content);
+        if let Some((start, end)) = spec.lines(&text, 0)? {
+            let selected: usize = text
+                .lines()
+                .skip(start - 1)
+                .take(end + 1 - start)
+                .map(|l| l.len() + 1)
+                .sum();
+            if selected as u64 > MAX_CONTEXT_FILE_BYTES {
+                return Err(FileCheckError::TooLarge(entry.clone()));
+            }
+        }
     }
 
     Ok(())
@@ -147,6 +337,52 @@ mod tests {
         assert!(!is_sensitive_path("tests/test_key_press.rs"));
     }
 
+    #[test]
+    fn file_specs_parse() {
+        assert_eq!(
+            FileSpec::parse("src/a.rs:10-20"),
+            FileSpec {
+                path: "src/a.rs",
+                selection: Some(Selection::Lines(10, 20))
+            }
+        );
+        assert_eq!(
+            FileSpec::parse("src/a.rs#parse_config"),
+            FileSpec {
+                path: "src/a.rs",
+                selection: Some(Selection::Symbol("parse_config"))
+            }
+        );
+        assert_eq!(FileSpec::parse("C:/a.rs").selection, None);
+        assert_eq!(FileSpec::parse("notes#").selection, None);
+    }
+
+    #[test]
+    fn symbols_span_their_block_and_docs() {
+        let rust = "use x;\n\n/// Doc.\n#[inline]\npub fn target(a: u8)\n    -> u8 {\n\
+                    { a }\n}\n\nfn other() {}\n";
+        assert_eq!(find_symbol(rust, "target"), Some((3, 8)));
+        assert_eq!(find_symbol(rust, "other"), Some((10, 10)));
+        assert_eq!(find_symbol(rust, "missing"), None);
+
+        let python =
+            "class A:\n    def run(self):\n        pass\n\n    def stop(self):\n        pass\n";
+        assert_eq!(find_symbol(python, "run"), Some((2, 3)));
+        assert_eq!(find_symbol(python, "A"), Some((1, 6)));
+    }
+
+    #[test]
+    fn line_ranges_are_checked_and_widened() {
+        let content = "1\n2\n3\n4\n5\n";
+        let spec = FileSpec::parse("f:2-3");
+        assert_eq!(spec.lines(content, 0).unwrap(), Some((2, 3)));
+        assert_eq!(spec.lines(content, 5).unwrap(), Some((1, 5)));
+        assert_eq!(FileSpec::parse("f:4-9").lines(content, 0).unwrap(), Some((4, 5)));
+        for bad in ["f:0-2", "f:3-2", "f:6-7"] {
+            assert!(FileSpec::parse(bad).lines(content, 0).is_err(), "{bad}");
+        }
+    }
+
     #[test]
     fn binary_detection() {
         assert!(is_likely_binary(&[0x00, 0x01, 0x02]));
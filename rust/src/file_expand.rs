@@ -0,0 +1,518 @@
+use crate::file_check::{is_likely_binary, is_sensitive_path, FileSpec, MAX_CONTEXT_FILE_BYTES};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Caps on the files a call's directories and globs may expand to.
+#[derive(Debug, Clone, Copy)]
+pub struct ExpandLimits {
+    pub max_files: usize,
+    pub max_bytes: u64,
+}
+
+/// `files` entries with directories and globs replaced by the files they
+/// hold, and a note for each file that was left out.
+#[derive(Debug, Default)]
+pub struct Expansion {
+    pub files: Vec<String>,
+    pub skipped: Vec<String>,
+}
+
+/// Whether `entry` is expanded rather than taken as a single file.
+fn is_expandable(entry: &str) -> bool {
+    let path = Path::new(entry);
+    if path.is_dir() {
+        return true;
+    }
+    !path.exists() && FileSpec::parse(entry).selection.is_none() && entry.contains(['*', '?', '['])
+}
+
+/// Directory entries one call's expansion may look at, so that a broad
+/// pattern cannot walk a huge tree.
+const MAX_VISITED_ENTRIES: usize = 20_000;
+
+/// Expand directories and glob patterns (`*`, `?`, `**`, `[abc]`) in
+/// `entries`. Other entries are kept as they are, for
+/// `validate_context_files` to check.
+///
+/// Hidden files, symlinks and those ignored by `.gitignore` or `.ignore`
+/// files are left out quietly; sensitive, binary and oversized files are
+/// listed in `skipped`. The walk stops once `limits` or
+/// `MAX_VISITED_ENTRIES` are reached, noting so in `skipped`. An entry that
+/// matches nothing is an error. This reads the disk; run it off the async
+/// runtime.
+pub fn expand_context_files(entries: &[String], limits: ExpandLimits) -> Result<Expansion, String> {
+    let mut collector = Collector {
+        limits,
+        expansion: Expansion::default(),
+        total_bytes: 0,
+        visited: 0,
+        matched: 0,
+        stopped: None,
+    };
+
+    for entry in entries {
+        if !is_expandable(entry) {
+            if !collector.expansion.files.contains(entry) {
+                collector.expansion.files.push(entry.clone());
+            }
+            continue;
+        }
+        if collector.stopped.is_some() {
+            continue;
+        }
+
+        let (root, pattern) = split_glob(entry);
+        // Walk the real path, so ignore files above a relative or symlinked
+        // root are found, but report paths as the entry spelled them
+        let Ok(real_root) = root.canonicalize() else {
+            return Err(format!("No files match {entry}"));
+        };
+        let walk_root = WalkRoot {
+            real: &real_root,
+            shown: &root,
+            pattern: pattern.as_deref(),
+        };
+        let mut rules = ancestor_rules(&real_root);
+        collector.matched = 0;
+        walk(&walk_root, &real_root, &mut rules, &mut collector);
+        if collector.matched == 0 && collector.stopped.is_none() {
+            return Err(format!("No files match {entry}"));
+        }
+    }
+
+    let mut expansion = collector.expansion;
+    expansion.skipped.extend(collector.stopped);
+    Ok(expansion)
+}
+
+/// Files taken so far by one expansion, and what is left of its limits.
+struct Collector {
+    limits: ExpandLimits,
+    expansion: Expansion,
+    total_bytes: u64,
+    /// Directory entries looked at, across all entries.
+    visited: usize,
+    /// Files the current entry matched, taken or not.
+    matched: usize,
+    /// Why the expansion stopped early, if it did.
+    stopped: Option<String>,
+}
+
+impl Collector {
+    /// Take or skip one matching file. Returns `false` once a limit is
+    /// reached and the walk should stop.
+    fn add(&mut self, path: PathBuf) -> bool {
+        self.matched += 1;
+        let name = path.to_string_lossy().into_owned();
+        let expansion = &mut self.expansion;
+        if expansion.files.contains(&name) {
+            return true;
+        }
+        if is_sensitive_path(&name) {
+            expansion.skipped.push(format!("{name} (sensitive)"));
+            return true;
+        }
+        let Ok(meta) = fs::metadata(&path) else {
+            expansion.skipped.push(format!("{name} (unreadable)"));
+            return true;
+        };
+        if meta.len() > MAX_CONTEXT_FILE_BYTES {
+            expansion
+                .skipped
+                .push(format!("{name} (over {MAX_CONTEXT_FILE_BYTES} bytes)"));
+            return true;
+        }
+        let limits = self.limits;
+        if expansion.files.len() >= limits.max_files
+            || self.total_bytes + meta.len() > limits.max_bytes
+        {
+            self.stopped = Some(format!(
+                "{name} and any further matches (limit of {} files or {} bytes reached)",
+                limits.max_files, limits.max_bytes
+            ));
+            return false;
+        }
+        match fs::read(&path) {
+            Ok(content) if is_likely_binary(&content) => {
+                expansion.skipped.push(format!("{name} (binary)"));
+            }
+            Ok(_) => {
+                self.total_bytes += meta.len();
+                expansion.files.push(name);
+            }
+            Err(_) => expansion.skipped.push(format!("{name} (unreadable)")),
+        }
+        true
+    }
+}
+
+/// Split a glob into the directory before its first wildcard and the
+/// pattern for paths below it; a directory has no pattern.
+fn split_glob(entry: &str) -> (PathBuf, Option<String>) {
+    if Path::new(entry).is_dir() {
+        return (PathBuf::from(entry), None);
+    }
+    let components: Vec<&str> = entry.split('/').collect();
+    let literal = components
+        .iter()
+        .position(|c| c.contains(['*', '?', '[']))
+        .unwrap_or(components.len());
+    let root = components[..literal].join("/");
+    let root = match (root.is_empty(), entry.starts_with('/')) {
+        (false, _) => root,
+        (true, true) => "/".to_string(),
+        (true, false) => ".".to_string(),
+    };
+    (PathBuf::from(root), Some(components[literal..].join("/")))
+}
+
+/// One line of a `.gitignore` or `.ignore` file.
+#[derive(Debug)]
+struct IgnoreRule {
+    /// Directory holding the ignore file.
+    base: PathBuf,
+    pattern: String,
+    negate: bool,
+    dir_only: bool,
+    /// Matched against the path below `base` rather than the file name.
+    anchored: bool,
+}
+
+impl IgnoreRule {
+    fn parse(base: &Path, line: &str) -> Option<IgnoreRule> {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        let (negate, line) = match line.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, line.strip_prefix('\\').unwrap_or(line)),
+        };
+        let (dir_only, line) = match line.strip_suffix('/') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        let anchored = line.contains('/');
+        let pattern = line.strip_prefix('/').unwrap_or(line);
+        if pattern.is_empty() {
+            return None;
+        }
+        Some(IgnoreRule {
+            base: base.to_path_buf(),
+            pattern: pattern.to_string(),
+            negate,
+            dir_only,
+            anchored,
+        })
+    }
+
+    fn matches(&self, path: &Path, is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+        let Ok(rel) = path.strip_prefix(&self.base) else {
+            return false;
+        };
+        let rel = rel.to_string_lossy();
+        if self.anchored {
+            glob_match(self.pattern.as_bytes(), rel.as_bytes())
+        } else {
+            let name = rel.rsplit('/').next().unwrap_or_default();
+            glob_match(self.pattern.as_bytes(), name.as_bytes())
+        }
+    }
+}
+
+fn read_rules(dir: &Path) -> Vec<IgnoreRule> {
+    [".gitignore", ".ignore"]
+        .iter()
+        .filter_map(|name| fs::read_to_string(dir.join(name)).ok())
+        .flat_map(|content| {
+            content
+                .lines()
+                .filter_map(|line| IgnoreRule::parse(dir, line))
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+/// Rules from the ignore files between the enclosing git repository's root
+/// and `dir`, outermost first. Outside a repository only `dir`'s own apply.
+fn ancestor_rules(dir: &Path) -> Vec<IgnoreRule> {
+    let repo_root = dir.ancestors().find(|d| d.join(".git").exists());
+    let mut dirs: Vec<&Path> = match repo_root {
+        Some(root) => dir.ancestors().take_while(|d| *d != root).collect(),
+        None => Vec::new(),
+    };
+    dirs.extend(repo_root);
+    dirs.reverse();
+    // `dir`'s own rules are read by `walk`
+    dirs.pop_if(|d| *d == dir);
+    dirs.into_iter().flat_map(read_rules).collect()
+}
+
+fn is_ignored(path: &Path, is_dir: bool, rules: &[IgnoreRule]) -> bool {
+    rules
+        .iter()
+        .rev()
+        .find(|rule| rule.matches(path, is_dir))
+        .is_some_and(|rule| !rule.negate)
+}
+
+/// Where a walk started: the canonical directory it reads, the directory
+/// as the caller wrote it, and the pattern paths below it must match.
+struct WalkRoot<'a> {
+    real: &'a Path,
+    shown: &'a Path,
+    pattern: Option<&'a str>,
+}
+
+/// Hand the files below `dir`, in name order, that are neither hidden,
+/// symlinks nor ignored and match the root's pattern to `collector`.
+/// Returns `false` once the collector or the entry cap stops the walk.
+fn walk(
+    root: &WalkRoot<'_>,
+    dir: &Path,
+    rules: &mut Vec<IgnoreRule>,
+    collector: &mut Collector,
+) -> bool {
+    let outer = rules.len();
+    rules.extend(read_rules(dir));
+    let mut entries: Vec<_> = match fs::read_dir(dir) {
+        Ok(entries) => entries.filter_map(Result::ok).collect(),
+        Err(_) => Vec::new(),
+    };
+    entries.sort_by_key(|e| e.file_name());
+    let mut go_on = true;
+    for entry in entries {
+        collector.visited += 1;
+        if collector.visited > MAX_VISITED_ENTRIES {
+            collector.stopped = Some(format!(
+                "the rest of {} (looked at {MAX_VISITED_ENTRIES} entries; narrow the pattern)",
+                root.shown.display()
+            ));
+            go_on = false;
+            break;
+        }
+        let path = entry.path();
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+        let is_dir = file_type.is_dir();
+        let hidden = entry.file_name().to_string_lossy().starts_with('.');
+        if hidden || file_type.is_symlink() || is_ignored(&path, is_dir, rules) {
+            continue;
+        }
+        let rel = path.strip_prefix(root.real).unwrap_or(&path);
+        if is_dir {
+            go_on = walk(root, &path, rules, collector);
+        } else if root
+            .pattern
+            .is_none_or(|p| glob_match(p.as_bytes(), rel.to_string_lossy().as_bytes()))
+        {
+            go_on = collector.add(root.shown.join(rel));
+        }
+        if !go_on {
+            break;
+        }
+    }
+    rules.truncate(outer);
+    go_on
+}
+
+/// Match `text` against a glob: `*` and `?` stay within a path component,
+/// a `**` component matches any number of components, and `[...]` is a
+/// character class (`[!...]` negated).
+///
+/// Both levels use the greedy wildcard match that backtracks only to the
+/// last star, so the time is bounded by the product of the lengths.
+fn glob_match(pattern: &[u8], text: &[u8]) -> bool {
+    let pattern: Vec<&[u8]> = pattern.split(|&c| c == b'/').collect();
+    let text: Vec<&[u8]> = text.split(|&c| c == b'/').collect();
+    let (mut p, mut t) = (0, 0);
+    // Pattern index after the last `**` and the text index it resumes from
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        if pattern.get(p) == Some(&&b"**"[..]) {
+            star = Some((p + 1, t));
+            p += 1;
+        } else if pattern.get(p).is_some_and(|seg| component_match(seg, text[t])) {
+            p += 1;
+            t += 1;
+        } else if let Some((after, from)) = star {
+            star = Some((after, from + 1));
+            p = after;
+            t = from + 1;
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|seg| *seg == b"**")
+}
+
+/// [`glob_match`] within one path component.
+fn component_match(pattern: &[u8], text: &[u8]) -> bool {
+    let (mut p, mut t) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        if pattern.get(p) == Some(&b'*') {
+            star = Some((p + 1, t));
+            p += 1;
+        } else if let Some(next) = match_one(pattern, p, text[t]) {
+            p = next;
+            t += 1;
+        } else if let Some((after, from)) = star {
+            star = Some((after, from + 1));
+            p = after;
+            t = from + 1;
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&c| c == b'*')
+}
+
+/// If the pattern token at `p` (not a `*`) matches `c`, the index of the
+/// token after it.
+fn match_one(pattern: &[u8], p: usize, c: u8) -> Option<usize> {
+    match *pattern.get(p)? {
+        b'?' => Some(p + 1),
+        b'[' => {
+            let Some(close) = pattern[p..].iter().skip(2).position(|&c| c == b']') else {
+                return (c == b'[').then_some(p + 1);
+            };
+            let end = p + close + 2;
+            let class = &pattern[p + 1..end];
+            let (negated, class) = match class.strip_prefix(b"!") {
+                Some(rest) => (true, rest),
+                None => (false, class),
+            };
+            let mut hit = false;
+            let mut i = 0;
+            while i < class.len() {
+                if class.get(i + 1) == Some(&b'-') && i + 2 < class.len() {
+                    hit |= (class[i]..=class[i + 2]).contains(&c);
+                    i += 3;
+                } else {
+                    hit |= class[i] == c;
+                    i += 1;
+                }
+            }
+            (hit != negated).then_some(end + 1)
+        }
+        b'\\' if p + 1 < pattern.len() => (pattern[p + 1] == c).then_some(p + 2),
+        literal => (literal == c).then_some(p + 1),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn globs_match_like_git() {
+        assert!(glob_match(b"*.rs", b"main.rs"));
+        assert!(!glob_match(b"*.rs", b"src/main.rs"));
+        assert!(glob_match(b"src/**/*.rs", b"src/main.rs"));
+        assert!(glob_match(b"src/**/*.rs", b"src/a/b/main.rs"));
+        assert!(glob_match(b"**/test_?.py", b"x/test_a.py"));
+        assert!(glob_match(b"[a-c]x[!0-9]", b"bxz"));
+        assert!(!glob_match(b"[a-c]x[!0-9]", b"bx5"));
+        assert!(glob_match(b"lib/**", b"lib/a/b"));
+        assert!(glob_match(b"a/**/b/**/c", b"a/x/b/y/z/c"));
+        assert!(!glob_match(b"a/**/b/**/c", b"a/x/c"));
+        assert!(glob_match(b"\\*.md", b"*.md"));
+        assert!(!glob_match(b"\\*.md", b"a.md"));
+    }
+
+    #[test]
+    fn pathological_globs_finish_quickly() {
+        let text = format!("{}{}", "a/".repeat(30), "a".repeat(200));
+        let start = std::time::Instant::now();
+        assert!(!glob_match(b"**/*a*a*a*b", text.as_bytes()));
+        assert!(!glob_match(b"**/**/**/**/x/*a*a*a*a*b", text.as_bytes()));
+        assert!(start.elapsed() < std::time::Duration::from_secs(1));
+    }
+
+    #[test]
+    fn directories_expand_honoring_ignores_and_caps() {
+        let dir = std::env::temp_dir().join(format!("grey-rso-expand-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let src = dir.join("src");
+        fs::create_dir_all(dir.join(".git")).unwrap();
+        fs::create_dir_all(src.join("gen")).unwrap();
+        fs::write(dir.join(".gitignore"), "*.log\n").unwrap();
+        fs::write(src.join(".ignore"), "gen/\n!keep.log\n").unwrap();
+        for file in [
+            "a.rs", "b.rs", "c.rs", "keep.log", "drop.log", "gen/x.rs", ".env", "key.pem",
+        ] {
+            fs::write(src.join(file), "text").unwrap();
+        }
+        fs::write(src.join("img.bin"), [0u8, 1, 2]).unwrap();
+        let name = |f: &str| src.join(f).to_string_lossy().into_owned();
+        let limits = ExpandLimits {
+            max_files: 10,
+            max_bytes: 1000,
+        };
+
+        let expansion = expand_context_files(&[name("")], limits).unwrap();
+        assert_eq!(
+            expansion.files,
+            [name("a.rs"), name("b.rs"), name("c.rs"), name("keep.log")]
+        );
+        assert_eq!(
+            expansion.skipped,
+            [
+                format!("{} (binary)", name("img.bin")),
+                format!("{} (sensitive)", name("key.pem")),
+            ]
+        );
+
+        let root = dir.to_string_lossy().into_owned();
+        let limits = ExpandLimits {
+            max_files: 2,
+            ..limits
+        };
+        let expansion = expand_context_files(&[format!("{root}/**/*.rs")], limits).unwrap();
+        assert_eq!(expansion.files, [name("a.rs"), name("b.rs")]);
+        assert_eq!(
+            expansion.skipped,
+            [format!(
+                "{} and any further matches (limit of 2 files or 1000 bytes reached)",
+                name("c.rs")
+            )]
+        );
+        let err = expand_context_files(&[format!("{root}/*.py")], limits).unwrap_err();
+        assert!(err.starts_with("No files match"), "{err}");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinks_are_skipped_and_linked_roots_keep_outer_ignores() {
+        use std::os::unix::fs::symlink;
+
+        let dir = std::env::temp_dir().join(format!("grey-rso-links-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let src = dir.join("repo/src");
+        fs::create_dir_all(dir.join("repo/.git")).unwrap();
+        fs::create_dir_all(&src).unwrap();
+        fs::create_dir_all(dir.join("home/.aws")).unwrap();
+        fs::write(dir.join("repo/.gitignore"), "*.log\n").unwrap();
+        fs::write(src.join("a.rs"), "text").unwrap();
+        fs::write(src.join("drop.log"), "text").unwrap();
+        fs::write(dir.join("home/.aws/credentials"), "secret").unwrap();
+        symlink(dir.join("home/.aws/credentials"), src.join("notes.txt")).unwrap();
+        symlink(&src, dir.join("link")).unwrap();
+        let limits = ExpandLimits {
+            max_files: 10,
+            max_bytes: 1000,
+        };
+
+        let link = dir.join("link").to_string_lossy().into_owned();
+        let expansion = expand_context_files(std::slice::from_ref(&link), limits).unwrap();
+        assert_eq!(expansion.files, [format!("{link}/a.rs")]);
+        assert!(expansion.skipped.is_empty(), "{:?}", expansion.skipped);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
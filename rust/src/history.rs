@@ -0,0 +1,442 @@
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use serde::{Deserialize, Serialize};
+
+use crate::logger::log_dir;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HistoryStatus {
+    Succeeded,
+    Failed,
+    Cancelled,
+}
+
+impl std::fmt::Display for HistoryStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            HistoryStatus::Succeeded => "succeeded",
+            HistoryStatus::Failed => "failed",
+            HistoryStatus::Cancelled => "cancelled",
+        };
+        f.write_str(name)
+    }
+}
+
+impl std::str::FromStr for HistoryStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "succeeded" | "success" | "ok" => Ok(HistoryStatus::Succeeded),
+            "failed" | "error" => Ok(HistoryStatus::Failed),
+            "cancelled" | "canceled" => Ok(HistoryStatus::Cancelled),
+            other => Err(format!(
+                "unknown status `{other}`; use succeeded, failed or cancelled"
+            )),
+        }
+    }
+}
+
+/// One recorded consultation, stored as a line of `history.jsonl`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub id: String,
+    /// Alias that answered, after any fallbacks.
+    pub alias: String,
+    pub model: String,
+    /// RFC 3339 start and end of the run.
+    pub started_at: String,
+    pub ended_at: String,
+    pub duration_secs: f64,
+    pub status: HistoryStatus,
+    pub prompt: String,
+    #[serde(default)]
+    pub files: Vec<String>,
+    /// Hash of the git diff sent as context, if any.
+    #[serde(default)]
+    pub diff_hash: Option<String>,
+    #[serde(default)]
+    pub thread_id: Option<String>,
+    #[serde(default)]
+    pub input_tokens: Option<u64>,
+    #[serde(default)]
+    pub output_tokens: Option<u64>,
+    #[serde(default)]
+    pub response: Option<String>,
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+/// First line of `prompt`, shortened for listings.
+fn summarize(prompt: &str) -> String {
+    let question = prompt.lines().next().unwrap_or_default();
+    if question.chars().count() > 60 {
+        question.chars().take(57).chain("...".chars()).collect()
+    } else {
+        question.to_string()
+    }
+}
+
+impl HistoryEntry {
+    /// First line of the prompt, shortened for listings.
+    pub fn summary(&self) -> String {
+        summarize(&self.prompt)
+    }
+
+    /// The whole consultation as Markdown: metadata, prompt and answer.
+    pub fn render(&self) -> String {
+        let mut out = format!("id:       {}\n", self.id);
+        out.push_str(&format!("model:    {} ({})\n", self.alias, self.model));
+        out.push_str(&format!("started:  {}\n", self.started_at));
+        out.push_str(&format!(
+            "ended:    {} ({:.1}s)\n",
+            self.ended_at, self.duration_secs
+        ));
+        out.push_str(&format!("status:   {}\n", self.status));
+        if let Some(ref thread_id) = self.thread_id {
+            out.push_str(&format!("thread:   {thread_id}\n"));
+        }
+        if !self.files.is_empty() {
+            out.push_str(&format!("files:    {}\n", self.files.join(", ")));
+        }
+        if let Some(ref hash) = self.diff_hash {
+            out.push_str(&format!("diff:     {hash}\n"));
+        }
+        if let (Some(input), Some(output)) = (self.input_tokens, self.output_tokens) {
+            out.push_str(&format!("tokens:   {input} in, {output} out\n"));
+        }
+        out.push_str(&format!("\n## Prompt\n\n{}\n", self.prompt));
+        if let Some(ref response) = self.response {
+            out.push_str(&format!("\n## Response\n\n{response}\n"));
+        }
+        if let Some(ref error) = self.error {
+            out.push_str(&format!("\n## Error\n\n{error}\n"));
+        }
+        out
+    }
+}
+
+/// The fields of a [`HistoryEntry`] a listing shows, read without the
+/// response, error and file list.
+#[derive(Debug, Clone, Deserialize)]
+pub struct HistoryHeader {
+    pub id: String,
+    pub alias: String,
+    pub model: String,
+    pub started_at: String,
+    pub status: HistoryStatus,
+    pub prompt: String,
+}
+
+impl HistoryHeader {
+    /// First line of the prompt, shortened for listings.
+    pub fn summary(&self) -> String {
+        summarize(&self.prompt)
+    }
+}
+
+/// Which entries `grey-rso history list` shows. Unset fields match anything.
+#[derive(Debug, Default)]
+pub struct HistoryFilter {
+    /// Alias or model name, case-insensitive.
+    pub model: Option<String>,
+    /// Earliest and latest start date, as `YYYY-MM-DD` (inclusive).
+    pub since: Option<String>,
+    pub until: Option<String>,
+    pub status: Option<HistoryStatus>,
+    /// Case-insensitive text looked for in the prompt, response and error.
+    pub text: Option<String>,
+}
+
+impl HistoryFilter {
+    pub fn matches(&self, entry: &HistoryEntry) -> bool {
+        let date = entry.started_at.get(..10).unwrap_or(&entry.started_at);
+        self.model.as_ref().is_none_or(|m| {
+            m.eq_ignore_ascii_case(&entry.alias) || m.eq_ignore_ascii_case(&entry.model)
+        }) && self.since.as_deref().is_none_or(|since| date >= since)
+            && self.until.as_deref().is_none_or(|until| date <= until)
+            && self.status.is_none_or(|s| s == entry.status)
+            && self.text.as_ref().is_none_or(|text| {
+                let needle = text.to_lowercase();
+                [
+                    Some(&entry.prompt),
+                    entry.response.as_ref(),
+                    entry.error.as_ref(),
+                ]
+                .into_iter()
+                .flatten()
+                .any(|haystack| haystack.to_lowercase().contains(&needle))
+            })
+    }
+}
+
+/// Append-only JSONL file of past consultations, one entry per line.
+///
+/// Once the file reaches half of `max_bytes` it is renamed to
+/// `history.1.jsonl`, replacing the previous one, and a new file is started;
+/// the two together hold the retained history.
+pub struct HistoryStore {
+    path: PathBuf,
+    max_bytes: u64,
+}
+
+impl HistoryStore {
+    pub fn new(path: PathBuf, max_bytes: u64) -> Self {
+        HistoryStore { path, max_bytes }
+    }
+
+    /// The store under the state directory, next to `mcp.log`.
+    pub fn open_default(max_bytes: u64) -> Self {
+        HistoryStore::new(log_dir().join("history.jsonl"), max_bytes)
+    }
+
+    /// The segment holding the entries before those in `path`.
+    fn rotated_path(&self) -> PathBuf {
+        self.path.with_extension("1.jsonl")
+    }
+
+    pub fn append(&self, entry: &HistoryEntry) -> io::Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        if fs::metadata(&self.path).is_ok_and(|m| m.len() >= self.max_bytes / 2) {
+            fs::rename(&self.path, self.rotated_path())?;
+        }
+        let mut line = serde_json::to_string(entry).map_err(io::Error::other)?;
+        line.push('\n');
+        // One write per entry, so concurrent appends do not interleave
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        file.write_all(line.as_bytes())
+    }
+
+    /// The stored lines, oldest first.
+    fn contents(&self) -> io::Result<String> {
+        let mut contents = String::new();
+        for path in [self.rotated_path(), self.path.clone()] {
+            match fs::read_to_string(&path) {
+                Ok(segment) => contents.push_str(&segment),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(contents)
+    }
+
+    /// Every entry, oldest first. Lines that do not parse are skipped.
+    pub fn entries(&self) -> io::Result<Vec<HistoryEntry>> {
+        Ok(self
+            .contents()?
+            .lines()
+            .filter_map(|line| serde_json::from_str(line).ok())
+            .collect())
+    }
+
+    /// Every entry's header, oldest first, for listings.
+    pub fn headers(&self) -> io::Result<Vec<HistoryHeader>> {
+        Ok(self
+            .contents()?
+            .lines()
+            .filter_map(|line| serde_json::from_str(line).ok())
+            .collect())
+    }
+
+    pub fn get(&self, id: &str) -> io::Result<Option<HistoryEntry>> {
+        // Only the lines that mention the id are parsed in full
+        let needle = serde_json::to_string(id).map_err(io::Error::other)?;
+        Ok(self
+            .contents()?
+            .lines()
+            .filter(|line| line.contains(&needle))
+            .filter_map(|line| serde_json::from_str::<HistoryEntry>(line).ok())
+            .find(|e| e.id == id))
+    }
+}
+
+/// One page of `entries` (oldest first, as stored), newest first.
+///
+/// `after` is the id of the last entry of the previous page; new entries
+/// appended meanwhile do not shift later pages. Returns the page and the
+/// cursor for the next one, or `None` when `after` is not a known id.
+pub fn page<'a>(
+    entries: &'a [HistoryHeader],
+    after: Option<&str>,
+    limit: usize,
+) -> Option<(Vec<&'a HistoryHeader>, Option<String>)> {
+    let end = match after {
+        Some(id) => entries.iter().position(|e| e.id == id)?,
+        None => entries.len(),
+    };
+    let start = end.saturating_sub(limit.max(1));
+    let items: Vec<&HistoryHeader> = entries[start..end].iter().rev().collect();
+    let next = (start > 0).then(|| entries[start].id.clone());
+    Some((items, next))
+}
+
+/// A new entry id: start time in milliseconds, process id and a sequence
+/// number, unique across concurrent servers.
+pub fn new_entry_id() -> String {
+    static SEQ: AtomicU64 = AtomicU64::new(0);
+    let millis = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+    format!(
+        "{millis}-{}-{}",
+        std::process::id(),
+        SEQ.fetch_add(1, Ordering::Relaxed)
+    )
+}
+
+/// Stable 64-bit FNV-1a hash of `text`, in hex.
+pub fn content_hash(text: &str) -> String {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in text.bytes() {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    format!("{hash:016x}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(id: &str, alias: &str, started_at: &str, status: HistoryStatus) -> HistoryEntry {
+        HistoryEntry {
+            id: id.into(),
+            alias: alias.into(),
+            model: format!("{alias}-model"),
+            started_at: started_at.into(),
+            ended_at: started_at.into(),
+            duration_secs: 1.0,
+            status,
+            prompt: format!("question {id}"),
+            files: vec![],
+            diff_hash: None,
+            thread_id: None,
+            input_tokens: None,
+            output_tokens: None,
+            response: Some("Use a Mutex".into()),
+            error: None,
+        }
+    }
+
+    #[test]
+    fn entries_round_trip_and_skip_bad_lines() {
+        let path = std::env::temp_dir().join(format!("grey-rso-history-{}", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let store = HistoryStore::new(path.clone(), u64::MAX);
+        assert!(store.entries().unwrap().is_empty());
+
+        store
+            .append(&entry("a", "gemini", "2026-01-02T03:04:05.000Z", HistoryStatus::Succeeded))
+            .unwrap();
+        OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(b"not json\n")
+            .unwrap();
+        store
+            .append(&entry("b", "codex", "2026-01-03T03:04:05.000Z", HistoryStatus::Failed))
+            .unwrap();
+
+        let ids: Vec<String> = store.entries().unwrap().into_iter().map(|e| e.id).collect();
+        assert_eq!(ids, ["a", "b"]);
+        assert_eq!(store.get("b").unwrap().unwrap().alias, "codex");
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn full_file_is_rotated_and_both_segments_are_read() {
+        let dir = std::env::temp_dir().join(format!("grey-rso-rotate-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let path = dir.join("history.jsonl");
+        let one = entry("a", "gemini", "2026-01-02T03:04:05.000Z", HistoryStatus::Succeeded);
+        let line_len = serde_json::to_string(&one).unwrap().len() as u64 + 1;
+        // Room for two entries per segment
+        let store = HistoryStore::new(path.clone(), line_len * 4);
+        for id in ["a", "b", "c", "d", "e"] {
+            store
+                .append(&entry(id, "gemini", "2026-01-02T03:04:05.000Z", HistoryStatus::Succeeded))
+                .unwrap();
+        }
+
+        let ids: Vec<String> = store.headers().unwrap().into_iter().map(|e| e.id).collect();
+        assert_eq!(ids, ["c", "d", "e"]);
+        assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 1);
+        assert!(store.get("a").unwrap().is_none());
+        assert_eq!(store.get("d").unwrap().unwrap().id, "d");
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn filter_combines_conditions() {
+        let e = entry("a", "gemini", "2026-01-02T03:04:05.000Z", HistoryStatus::Succeeded);
+        assert!(HistoryFilter::default().matches(&e));
+        assert!(HistoryFilter {
+            model: Some("GEMINI-model".into()),
+            since: Some("2026-01-02".into()),
+            until: Some("2026-01-02".into()),
+            status: Some(HistoryStatus::Succeeded),
+            text: Some("mutex".into()),
+        }
+        .matches(&e));
+        assert!(!HistoryFilter {
+            since: Some("2026-01-03".into()),
+            ..HistoryFilter::default()
+        }
+        .matches(&e));
+        assert!(!HistoryFilter {
+            text: Some("rwlock".into()),
+            ..HistoryFilter::default()
+        }
+        .matches(&e));
+    }
+
+    fn header(id: &str) -> HistoryHeader {
+        let entry = entry(id, "gemini", "2026-01-02T03:04:05.000Z", HistoryStatus::Succeeded);
+        serde_json::from_value(serde_json::to_value(entry).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn pages_run_newest_first_and_ignore_appends() {
+        let mut entries: Vec<HistoryHeader> =
+            ["a", "b", "c", "d", "e"].iter().map(|id| header(id)).collect();
+        let ids = |page: &[&HistoryHeader]| page.iter().map(|e| e.id.clone()).collect::<Vec<_>>();
+
+        let (first, next) = page(&entries, None, 2).unwrap();
+        assert_eq!(ids(&first), ["e", "d"]);
+        assert_eq!(next.as_deref(), Some("d"));
+
+        entries.push(header("f"));
+        let (second, next) = page(&entries, next.as_deref(), 2).unwrap();
+        assert_eq!(ids(&second), ["c", "b"]);
+        let (last, next) = page(&entries, next.as_deref(), 2).unwrap();
+        assert_eq!(ids(&last), ["a"]);
+        assert_eq!(next, None);
+        assert!(page(&entries, Some("zzz"), 2).is_none());
+    }
+
+    #[test]
+    fn render_includes_prompt_and_response() {
+        let text = entry("a", "gemini", "2026-01-02T03:04:05.000Z", HistoryStatus::Succeeded).render();
+        assert!(text.contains("model:    gemini (gemini-model)"));
+        assert!(text.contains("## Prompt\n\nquestion a"));
+        assert!(text.contains("## Response\n\nUse a Mutex"));
+        assert!(!text.contains("## Error"));
+    }
+
+    #[test]
+    fn content_hash_is_stable() {
+        assert_eq!(content_hash(""), "cbf29ce484222325");
+        assert_ne!(content_hash("a"), content_hash("b"));
+    }
+}
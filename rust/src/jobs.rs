@@ -0,0 +1,196 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime};
+
+use serde_json::Value;
+use tokio::sync::watch;
+use tokio_util::sync::CancellationToken;
+
+use crate::cli_exec::CliProgress;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobState {
+    Running,
+    Succeeded,
+    Failed,
+    Cancelled,
+}
+
+impl std::fmt::Display for JobState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            JobState::Running => "running",
+            JobState::Succeeded => "succeeded",
+            JobState::Failed => "failed",
+            JobState::Cancelled => "cancelled",
+        };
+        f.write_str(name)
+    }
+}
+
+/// A consult started with `consult_start`.
+struct Job {
+    alias: String,
+    started: Instant,
+    started_at: SystemTime,
+    cancel: CancellationToken,
+    progress: watch::Receiver<CliProgress>,
+    state: JobState,
+    /// Formatted tool output, set once the job finishes.
+    output: Option<String>,
+    /// Structured counterpart of `output`.
+    report: Option<Value>,
+    finished: Option<Instant>,
+}
+
+/// Point-in-time view of a job, as reported by `consult_status`.
+#[derive(Debug, Clone)]
+pub struct JobSnapshot {
+    pub id: String,
+    pub alias: String,
+    pub state: JobState,
+    pub started_at: SystemTime,
+    pub elapsed: Duration,
+    pub progress: CliProgress,
+    pub output: Option<String>,
+    pub report: Option<Value>,
+}
+
+/// Background consults, keyed by job id. Finished jobs are kept for
+/// `retention` so their results can still be fetched, then forgotten.
+pub struct JobTable {
+    jobs: Mutex<HashMap<String, Job>>,
+    next_id: AtomicU64,
+    retention: Duration,
+}
+
+impl JobTable {
+    pub fn new(retention: Duration) -> Self {
+        JobTable {
+            jobs: Mutex::new(HashMap::new()),
+            next_id: AtomicU64::new(1),
+            retention,
+        }
+    }
+
+    pub fn retention(&self) -> Duration {
+        self.retention
+    }
+
+    /// Register a running job and return its id.
+    pub fn insert(
+        &self,
+        alias: &str,
+        cancel: CancellationToken,
+        progress: watch::Receiver<CliProgress>,
+    ) -> String {
+        let id = format!("job-{}", self.next_id.fetch_add(1, Ordering::Relaxed));
+        let job = Job {
+            alias: alias.to_string(),
+            started: Instant::now(),
+            started_at: SystemTime::now(),
+            cancel,
+            progress,
+            state: JobState::Running,
+            output: None,
+            report: None,
+            finished: None,
+        };
+        let mut jobs = self.jobs.lock().unwrap();
+        self.prune(&mut jobs);
+        jobs.insert(id.clone(), job);
+        id
+    }
+
+    /// Record the outcome of a job. Jobs already forgotten are ignored.
+    pub fn finish(&self, id: &str, state: JobState, output: String, report: Value) {
+        let mut jobs = self.jobs.lock().unwrap();
+        if let Some(job) = jobs.get_mut(id) {
+            job.state = state;
+            job.output = Some(output);
+            job.report = Some(report);
+            job.finished = Some(Instant::now());
+        }
+    }
+
+    pub fn snapshot(&self, id: &str) -> Option<JobSnapshot> {
+        let mut jobs = self.jobs.lock().unwrap();
+        self.prune(&mut jobs);
+        let job = jobs.get(id)?;
+        let elapsed = match job.finished {
+            Some(finished) => finished - job.started,
+            None => job.started.elapsed(),
+        };
+        let progress = job.progress.borrow().clone();
+        Some(JobSnapshot {
+            id: id.to_string(),
+            alias: job.alias.clone(),
+            state: job.state,
+            started_at: job.started_at,
+            elapsed,
+            progress,
+            output: job.output.clone(),
+            report: job.report.clone(),
+        })
+    }
+
+    /// Ask a running job to stop. Returns `None` for unknown ids and the
+    /// job's state otherwise; the state only changes once the CLI has exited.
+    pub fn cancel(&self, id: &str) -> Option<JobState> {
+        let jobs = self.jobs.lock().unwrap();
+        let job = jobs.get(id)?;
+        job.cancel.cancel();
+        Some(job.state)
+    }
+
+    fn prune(&self, jobs: &mut HashMap<String, Job>) {
+        jobs.retain(|_, job| {
+            job.finished
+                .is_none_or(|finished| finished.elapsed() < self.retention)
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn start(table: &JobTable) -> (String, CancellationToken) {
+        let cancel = CancellationToken::new();
+        let (_tx, rx) = watch::channel(CliProgress::default());
+        (table.insert("gemini", cancel.clone(), rx), cancel)
+    }
+
+    #[test]
+    fn finished_jobs_keep_their_output() {
+        let table = JobTable::new(Duration::from_secs(60));
+        let (id, _) = start(&table);
+        assert_eq!(table.snapshot(&id).unwrap().state, JobState::Running);
+
+        table.finish(&id, JobState::Succeeded, "answer".into(), Value::Null);
+        let snapshot = table.snapshot(&id).unwrap();
+        assert_eq!(snapshot.state, JobState::Succeeded);
+        assert_eq!(snapshot.output.as_deref(), Some("answer"));
+        assert!(table.snapshot("job-999").is_none());
+    }
+
+    #[test]
+    fn finished_jobs_expire_after_retention() {
+        let table = JobTable::new(Duration::ZERO);
+        let (finished, _) = start(&table);
+        let (running, _) = start(&table);
+        table.finish(&finished, JobState::Failed, "error".into(), Value::Null);
+        assert!(table.snapshot(&finished).is_none());
+        assert!(table.snapshot(&running).is_some());
+    }
+
+    #[test]
+    fn cancel_signals_the_job() {
+        let table = JobTable::new(Duration::from_secs(60));
+        let (id, token) = start(&table);
+        assert_eq!(table.cancel(&id), Some(JobState::Running));
+        assert!(token.is_cancelled());
+        assert_eq!(table.cancel("job-999"), None);
+    }
+}
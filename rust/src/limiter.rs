@@ -0,0 +1,182 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio_util::sync::CancellationToken;
+
+use crate::config::Config;
+
+/// Caps how many consultant CLIs run at once, server-wide and per alias.
+///
+/// Callers beyond the cap wait in FIFO order (tokio semaphores are fair);
+/// once `max_queue_depth` callers are waiting, further ones are turned away.
+pub struct ConcurrencyLimiter {
+    global: Arc<Semaphore>,
+    per_alias: HashMap<String, Arc<Semaphore>>,
+    max_queue_depth: usize,
+    waiting: AtomicUsize,
+}
+
+/// Permission to run one CLI; the slot is freed when this is dropped.
+pub struct Slot {
+    _alias: Option<OwnedSemaphorePermit>,
+    _global: OwnedSemaphorePermit,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum AcquireError {
+    QueueFull { depth: usize },
+    Cancelled,
+}
+
+/// Counts a caller as waiting for as long as it is alive.
+struct Waiting<'a>(&'a AtomicUsize);
+
+impl Drop for Waiting<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl ConcurrencyLimiter {
+    pub fn new(config: &Config) -> Self {
+        let per_alias = config
+            .max_concurrent_per_alias
+            .iter()
+            .map(|(alias, &cap)| (alias.clone(), Arc::new(Semaphore::new(cap.max(1)))))
+            .collect();
+        ConcurrencyLimiter {
+            global: Arc::new(Semaphore::new(config.max_concurrent)),
+            per_alias,
+            max_queue_depth: config.max_queue_depth,
+            waiting: AtomicUsize::new(0),
+        }
+    }
+
+    /// Wait for a slot to run `alias`, or give up when the queue is full or
+    /// `cancel` fires.
+    pub async fn acquire(
+        &self,
+        alias: &str,
+        cancel: &CancellationToken,
+    ) -> Result<Slot, AcquireError> {
+        let alias_sem = self.per_alias.get(alias);
+
+        // A permit is only ever free when nobody is queued, so taking it
+        // directly never jumps the queue.
+        let alias_permit = match alias_sem {
+            Some(sem) => sem.clone().try_acquire_owned().ok().map(Some),
+            None => Some(None),
+        };
+        if let Some(alias_permit) = alias_permit {
+            if let Ok(global) = self.global.clone().try_acquire_owned() {
+                return Ok(Slot {
+                    _alias: alias_permit,
+                    _global: global,
+                });
+            }
+        }
+
+        if self.waiting.fetch_add(1, Ordering::SeqCst) >= self.max_queue_depth {
+            self.waiting.fetch_sub(1, Ordering::SeqCst);
+            return Err(AcquireError::QueueFull {
+                depth: self.max_queue_depth,
+            });
+        }
+        let _waiting = Waiting(&self.waiting);
+
+        let wait = async {
+            let alias_permit = match alias_sem {
+                Some(sem) => Some(
+                    sem.clone()
+                        .acquire_owned()
+                        .await
+                        .expect("limiter semaphores are never closed"),
+                ),
+                None => None,
+            };
+            let global = self
+                .global
+                .clone()
+                .acquire_owned()
+                .await
+                .expect("limiter semaphores are never closed");
+            (alias_permit, global)
+        };
+        tokio::select! {
+            (alias_permit, global) = wait => Ok(Slot {
+                _alias: alias_permit,
+                _global: global,
+            }),
+            _ = cancel.cancelled() => Err(AcquireError::Cancelled),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn limiter(global: usize, gemini: Option<usize>, queue: usize) -> ConcurrencyLimiter {
+        let mut config = Config {
+            max_concurrent: global,
+            max_queue_depth: queue,
+            ..Config::default()
+        };
+        if let Some(cap) = gemini {
+            config.max_concurrent_per_alias.insert("gemini".into(), cap);
+        }
+        ConcurrencyLimiter::new(&config)
+    }
+
+    #[tokio::test]
+    async fn waits_for_a_free_slot_in_order() {
+        let limiter = Arc::new(limiter(1, None, 4));
+        let cancel = CancellationToken::new();
+        let first = limiter.acquire("gemini", &cancel).await.unwrap();
+
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        for n in 0..2 {
+            let limiter = limiter.clone();
+            let tx = tx.clone();
+            tokio::spawn(async move {
+                let _slot = limiter.acquire("codex", &CancellationToken::new()).await.unwrap();
+                tx.send(n).unwrap();
+            });
+            tokio::task::yield_now().await;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(rx.try_recv().is_err());
+
+        drop(first);
+        assert_eq!(rx.recv().await, Some(0));
+        assert_eq!(rx.recv().await, Some(1));
+    }
+
+    #[tokio::test]
+    async fn per_alias_cap_leaves_other_aliases_free() {
+        let limiter = limiter(4, Some(1), 0);
+        let cancel = CancellationToken::new();
+        let _gemini = limiter.acquire("gemini", &cancel).await.unwrap();
+        assert!(limiter.acquire("codex", &cancel).await.is_ok());
+        assert_eq!(
+            limiter.acquire("gemini", &cancel).await.err(),
+            Some(AcquireError::QueueFull { depth: 0 })
+        );
+    }
+
+    #[tokio::test]
+    async fn cancelled_waiter_leaves_the_queue() {
+        let limiter = limiter(1, None, 1);
+        let _held = limiter.acquire("gemini", &CancellationToken::new()).await.unwrap();
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+        assert_eq!(
+            limiter.acquire("gemini", &cancel).await.err(),
+            Some(AcquireError::Cancelled)
+        );
+        assert_eq!(limiter.waiting.load(Ordering::SeqCst), 0);
+    }
+}
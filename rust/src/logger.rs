@@ -2,7 +2,8 @@ use std::fs::{self, OpenOptions};
 use std::io::Write;
 use std::path::PathBuf;
 
-fn log_dir() -> PathBuf {
+/// State directory holding the log and consult history.
+pub fn log_dir() -> PathBuf {
     let state_home = std::env::var("XDG_STATE_HOME").ok().map(PathBuf::from);
     let base = state_home.unwrap_or_else(|| {
         dirs::home_dir()
@@ -60,6 +61,14 @@ pub fn log_response(model: &str, response: &str) {
     ));
 }
 
+pub fn log_cancellation(model: &str, elapsed: std::time::Duration) {
+    log_to_file(&format!(
+        "CANCELLED (model: {model}) after {:.1}s\n{}",
+        elapsed.as_secs_f64(),
+        "=".repeat(80)
+    ));
+}
+
 pub fn log_cli_debug(message: &str) {
     log_to_file(&format!("CLI DEBUG: {message}"));
 }
@@ -86,6 +95,21 @@ fn chrono_lite_now() -> String {
     format!("{year:04}-{month:02}-{day:02}T{hours:02}:{minutes:02}:{seconds:02}Z")
 }
 
+/// `t` as an RFC 3339 UTC timestamp with milliseconds.
+pub fn format_timestamp(t: std::time::SystemTime) -> String {
+    let d = t.duration_since(std::time::UNIX_EPOCH).unwrap_or_default();
+    let secs = d.as_secs();
+    let (year, month, day) = days_to_ymd(secs / 86400);
+    let time_of_day = secs % 86400;
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}.{:03}Z",
+        time_of_day / 3600,
+        (time_of_day % 3600) / 60,
+        time_of_day % 60,
+        d.subsec_millis()
+    )
+}
+
 fn days_to_ymd(days: u64) -> (u64, u64, u64) {
     // Algorithm from http://howardhinnant.github.io/date_algorithms.html
     let z = days + 719468;
@@ -100,3 +124,15 @@ fn days_to_ymd(days: u64) -> (u64, u64, u64) {
     let y = if m <= 2 { y + 1 } else { y };
     (y, m, d)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn timestamp_is_rfc3339_with_millis() {
+        let t = UNIX_EPOCH + Duration::from_millis(1_709_251_199_123);
+        assert_eq!(format_timestamp(t), "2024-02-29T23:59:59.123Z");
+    }
+}
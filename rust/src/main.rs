@@ -1,20 +1,29 @@
 mod cli_exec;
 mod config;
 mod file_check;
+mod file_expand;
 mod git_diff;
+mod history;
+mod jobs;
+mod limiter;
 mod logger;
 mod models;
+mod outcome;
 mod prompt;
+mod secrets;
 mod server;
 mod system_prompt;
+mod templates;
+mod threads;
 
 use clap::{Parser, Subcommand};
 use rmcp::ServiceExt;
 
 use config::{config_dir, load_config};
+use history::{HistoryEntry, HistoryFilter, HistoryStatus, HistoryStore};
 use logger::log_server_start;
 use server::{SecondOpinionServer, SERVER_VERSION};
-use system_prompt::init_system_prompt;
+use system_prompt::{init_mode_prompts, init_system_prompt};
 
 #[derive(Parser)]
 #[command(name = "grey-rso", version = SERVER_VERSION, about = "Second Opinion MCP server")]
@@ -26,7 +35,95 @@ struct Cli {
 #[derive(Subcommand)]
 enum Commands {
     /// Create default system prompt file
-    InitPrompt,
+    InitPrompt {
+        /// Also write every built-in mode's prompt to prompts/<mode>.md
+        #[arg(long)]
+        all: bool,
+    },
+    /// Browse past consultations
+    History {
+        #[command(subcommand)]
+        action: HistoryAction,
+    },
+}
+
+#[derive(Subcommand)]
+enum HistoryAction {
+    /// List consultations, newest first
+    List {
+        /// Only consults answered by this alias or model
+        #[arg(long)]
+        model: Option<String>,
+        /// Only consults started on or after this date (YYYY-MM-DD, UTC)
+        #[arg(long)]
+        since: Option<String>,
+        /// Only consults started on or before this date (YYYY-MM-DD, UTC)
+        #[arg(long)]
+        until: Option<String>,
+        /// Only consults that succeeded, failed or were cancelled
+        #[arg(long)]
+        status: Option<HistoryStatus>,
+        /// Only consults whose prompt, answer or error contains this text
+        #[arg(long)]
+        search: Option<String>,
+        /// Show at most this many entries (0 for all)
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+    },
+    /// Print one consultation in full
+    Show {
+        /// Entry id, as shown by `history list`
+        id: String,
+    },
+}
+
+fn print_history_line(entry: &HistoryEntry) {
+    println!(
+        "{}  {}  {:<9}  {} ({})  {:.1}s  {}",
+        entry.id,
+        entry.started_at.get(..19).unwrap_or(&entry.started_at),
+        entry.status,
+        entry.alias,
+        entry.model,
+        entry.duration_secs,
+        entry.summary(),
+    );
+}
+
+fn run_history(action: HistoryAction) -> std::io::Result<()> {
+    let store = HistoryStore::open_default(load_config().history_max_bytes);
+    match action {
+        HistoryAction::List {
+            model,
+            since,
+            until,
+            status,
+            search,
+            limit,
+        } => {
+            let filter = HistoryFilter {
+                model,
+                since,
+                until,
+                status,
+                text: search,
+            };
+            let entries = store.entries()?;
+            let matching = entries.iter().rev().filter(|e| filter.matches(e));
+            let limit = if limit == 0 { usize::MAX } else { limit };
+            for entry in matching.take(limit) {
+                print_history_line(entry);
+            }
+        }
+        HistoryAction::Show { id } => match store.get(&id)? {
+            Some(entry) => print!("{}", entry.render()),
+            None => {
+                eprintln!("No consultation with id {id}");
+                std::process::exit(1);
+            }
+        },
+    }
+    Ok(())
 }
 
 #[tokio::main(flavor = "current_thread")]
@@ -34,19 +131,36 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let cli = Cli::parse();
 
     match cli.command {
-        Some(Commands::InitPrompt) => {
+        Some(Commands::InitPrompt { all }) => {
             let dir = config_dir();
             match init_system_prompt(&dir) {
                 Ok(path) => {
                     println!("Created system prompt at: {}", path.display());
                     println!("You can now edit this file to customize the system prompt.");
                 }
+                // With --all an existing default prompt is kept and the modes still written
+                Err(e) if all => println!("{e}"),
                 Err(e) => {
                     eprintln!("{e}");
                     std::process::exit(1);
                 }
             }
+            if all {
+                match init_mode_prompts(&dir.join("prompts")) {
+                    Ok(paths) => {
+                        for (path, created) in paths {
+                            let action = if created { "Created" } else { "Kept existing" };
+                            println!("{action} mode prompt at: {}", path.display());
+                        }
+                    }
+                    Err(e) => {
+                        eprintln!("{e}");
+                        std::process::exit(1);
+                    }
+                }
+            }
         }
+        Some(Commands::History { action }) => run_history(action)?,
         None => {
             // Default: run MCP server on stdio
             let config = load_config();
@@ -2,6 +2,7 @@ use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fmt;
+use std::str::FromStr;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "lowercase")]
@@ -12,7 +13,6 @@ pub enum ModelAlias {
     Kilo,
 }
 
-#[cfg(test)]
 impl ModelAlias {
     pub const ALL: &[ModelAlias] = &[
         ModelAlias::Gemini,
@@ -33,6 +33,20 @@ impl fmt::Display for ModelAlias {
     }
 }
 
+impl FromStr for ModelAlias {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "gemini" => Ok(ModelAlias::Gemini),
+            "claude" => Ok(ModelAlias::Claude),
+            "codex" => Ok(ModelAlias::Codex),
+            "kilo" => Ok(ModelAlias::Kilo),
+            _ => Err(()),
+        }
+    }
+}
+
 pub const DEFAULT_ALIAS: ModelAlias = ModelAlias::Gemini;
 
 pub fn default_model_mapping() -> HashMap<ModelAlias, String> {
@@ -0,0 +1,253 @@
+use serde_json::Value;
+
+use crate::config::OutputFormat;
+
+/// A consultant's answer plus whatever metadata its CLI reported.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ConsultOutcome {
+    pub text: String,
+    /// Model name as reported by the CLI, which may differ from the one asked for.
+    pub model_reported: Option<String>,
+    pub input_tokens: Option<u64>,
+    pub output_tokens: Option<u64>,
+    /// Cost in USD, when the CLI reports it.
+    pub cost: Option<f64>,
+    pub session_id: Option<String>,
+}
+
+impl ConsultOutcome {
+    /// An answer with no metadata, from plain stdout.
+    pub fn from_text(text: &str) -> Self {
+        ConsultOutcome {
+            text: text.trim().to_string(),
+            ..ConsultOutcome::default()
+        }
+    }
+}
+
+/// Parse stdout of a CLI run in `format`.
+///
+/// Returns `None` when the output is not in the expected shape (plain text
+/// from an older CLI, say), so the caller can fall back to the raw text, and
+/// `Some(Err(message))` when the CLI reported an error inside its output.
+pub fn parse_output(format: OutputFormat, stdout: &str) -> Option<Result<ConsultOutcome, String>> {
+    match format {
+        OutputFormat::Text => None,
+        OutputFormat::ClaudeJson => parse_claude(stdout),
+        OutputFormat::GeminiJson => parse_gemini(stdout),
+        OutputFormat::CodexJson => parse_codex(stdout),
+    }
+}
+
+/// `claude --print --output-format json`: one result object.
+fn parse_claude(stdout: &str) -> Option<Result<ConsultOutcome, String>> {
+    let v: Value = serde_json::from_str(stdout.trim()).ok()?;
+    let text = v.get("result")?.as_str()?.trim().to_string();
+    if v.get("is_error").and_then(Value::as_bool) == Some(true) {
+        return Some(Err(text));
+    }
+    let usage = v.get("usage");
+    let input_tokens = usage.map(|u| {
+        [
+            "input_tokens",
+            "cache_creation_input_tokens",
+            "cache_read_input_tokens",
+        ]
+        .iter()
+        .filter_map(|k| u.get(k).and_then(Value::as_u64))
+        .sum()
+    });
+    // modelUsage is keyed by model; the main model is the one that wrote the most
+    let model_reported = v
+        .get("modelUsage")
+        .and_then(Value::as_object)
+        .and_then(|models| {
+            models
+                .iter()
+                .max_by_key(|(_, u)| u.get("outputTokens").and_then(Value::as_u64).unwrap_or(0))
+                .map(|(name, _)| name.clone())
+        });
+    Some(Ok(ConsultOutcome {
+        text,
+        model_reported,
+        input_tokens,
+        output_tokens: usage
+            .and_then(|u| u.get("output_tokens"))
+            .and_then(Value::as_u64),
+        cost: v.get("total_cost_usd").and_then(Value::as_f64),
+        session_id: v
+            .get("session_id")
+            .and_then(Value::as_str)
+            .map(str::to_string),
+    }))
+}
+
+/// `gemini -o json`: `{ "response": ..., "stats": { "models": { name: { "tokens": ... } } } }`.
+fn parse_gemini(stdout: &str) -> Option<Result<ConsultOutcome, String>> {
+    // Gemini may print status lines before the JSON document
+    let start = stdout.find('{')?;
+    let v: Value = serde_json::from_str(stdout[start..].trim()).ok()?;
+    if let Some(error) = v.get("error").filter(|e| !e.is_null()) {
+        let message = error
+            .get("message")
+            .and_then(Value::as_str)
+            .map(str::to_string)
+            .unwrap_or_else(|| error.to_string());
+        return Some(Err(message));
+    }
+    let text = v.get("response")?.as_str()?.trim().to_string();
+
+    let models = v
+        .pointer("/stats/models")
+        .and_then(Value::as_object)
+        .filter(|m| !m.is_empty());
+    let sum = |key: &str| {
+        models.map(|models| {
+            models
+                .values()
+                .filter_map(|m| m.pointer(&format!("/tokens/{key}")).and_then(Value::as_u64))
+                .sum()
+        })
+    };
+    let model_reported = models.and_then(|models| {
+        models
+            .iter()
+            .max_by_key(|(_, m)| {
+                m.pointer("/tokens/candidates")
+                    .and_then(Value::as_u64)
+                    .unwrap_or(0)
+            })
+            .map(|(name, _)| name.clone())
+    });
+    Some(Ok(ConsultOutcome {
+        text,
+        model_reported,
+        input_tokens: sum("prompt"),
+        output_tokens: sum("candidates"),
+        cost: None,
+        session_id: v
+            .get("session_id")
+            .and_then(Value::as_str)
+            .map(str::to_string),
+    }))
+}
+
+/// `codex exec --json`: one JSON event per line. The answer is the last agent
+/// message; usage arrives with `turn.completed`.
+fn parse_codex(stdout: &str) -> Option<Result<ConsultOutcome, String>> {
+    let mut outcome = ConsultOutcome::default();
+    let mut answer = None;
+    let mut saw_event = false;
+    for line in stdout.lines() {
+        let Ok(event) = serde_json::from_str::<Value>(line.trim()) else {
+            continue;
+        };
+        let Some(kind) = event.get("type").and_then(Value::as_str) else {
+            continue;
+        };
+        saw_event = true;
+        match kind {
+            "thread.started" => {
+                outcome.session_id = event
+                    .get("thread_id")
+                    .and_then(Value::as_str)
+                    .map(str::to_string);
+            }
+            "item.completed" => {
+                let item = event.get("item");
+                if item.and_then(|i| i.get("type")).and_then(Value::as_str) == Some("agent_message")
+                {
+                    answer = item
+                        .and_then(|i| i.get("text"))
+                        .and_then(Value::as_str)
+                        .map(str::to_string);
+                }
+            }
+            "turn.completed" => {
+                let usage = event.get("usage");
+                let tokens = |key: &str| usage.and_then(|u| u.get(key)).and_then(Value::as_u64);
+                outcome.input_tokens = tokens("input_tokens");
+                outcome.output_tokens = tokens("output_tokens");
+            }
+            "turn.failed" | "error" => {
+                let message = event
+                    .pointer("/error/message")
+                    .or_else(|| event.get("message"))
+                    .and_then(Value::as_str)
+                    .unwrap_or("codex reported an error");
+                return Some(Err(message.to_string()));
+            }
+            _ => {}
+        }
+    }
+    if !saw_event {
+        return None;
+    }
+    outcome.text = answer.unwrap_or_default().trim().to_string();
+    Some(Ok(outcome))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_claude_result() {
+        let stdout = r#"{"type":"result","subtype":"success","is_error":false,"result":"Use a mutex.","session_id":"abc","total_cost_usd":0.0123,"usage":{"input_tokens":10,"cache_read_input_tokens":90,"output_tokens":42},"modelUsage":{"claude-haiku":{"outputTokens":3},"claude-opus-4":{"outputTokens":42}}}"#;
+        let outcome = parse_output(OutputFormat::ClaudeJson, stdout)
+            .unwrap()
+            .unwrap();
+        assert_eq!(outcome.text, "Use a mutex.");
+        assert_eq!(outcome.model_reported.as_deref(), Some("claude-opus-4"));
+        assert_eq!(outcome.input_tokens, Some(100));
+        assert_eq!(outcome.output_tokens, Some(42));
+        assert_eq!(outcome.cost, Some(0.0123));
+        assert_eq!(outcome.session_id.as_deref(), Some("abc"));
+
+        let error = r#"{"type":"result","is_error":true,"result":"Invalid API key"}"#;
+        assert_eq!(
+            parse_output(OutputFormat::ClaudeJson, error),
+            Some(Err("Invalid API key".into()))
+        );
+    }
+
+    #[test]
+    fn parses_gemini_response_after_status_lines() {
+        let stdout = "Loaded cached credentials.\n{\"response\":\"42\",\"stats\":{\"models\":{\"gemini-2.5-pro\":{\"tokens\":{\"prompt\":120,\"candidates\":8}}}}}";
+        let outcome = parse_output(OutputFormat::GeminiJson, stdout)
+            .unwrap()
+            .unwrap();
+        assert_eq!(outcome.text, "42");
+        assert_eq!(outcome.model_reported.as_deref(), Some("gemini-2.5-pro"));
+        assert_eq!(outcome.input_tokens, Some(120));
+        assert_eq!(outcome.output_tokens, Some(8));
+    }
+
+    #[test]
+    fn parses_codex_event_stream() {
+        let stdout = [
+            r#"{"type":"thread.started","thread_id":"t-1"}"#,
+            r#"{"type":"item.completed","item":{"type":"reasoning","text":"thinking"}}"#,
+            r#"{"type":"item.completed","item":{"type":"agent_message","text":"Done."}}"#,
+            r#"{"type":"turn.completed","usage":{"input_tokens":50,"output_tokens":5}}"#,
+        ]
+        .join("\n");
+        let outcome = parse_output(OutputFormat::CodexJson, &stdout)
+            .unwrap()
+            .unwrap();
+        assert_eq!(outcome.text, "Done.");
+        assert_eq!(outcome.session_id.as_deref(), Some("t-1"));
+        assert_eq!(outcome.input_tokens, Some(50));
+        assert_eq!(outcome.output_tokens, Some(5));
+    }
+
+    #[test]
+    fn plain_text_is_not_parsed() {
+        assert_eq!(
+            parse_output(OutputFormat::ClaudeJson, "just an answer"),
+            None
+        );
+        assert_eq!(parse_output(OutputFormat::GeminiJson, "no json here"), None);
+        assert_eq!(parse_output(OutputFormat::CodexJson, "plain\nlines"), None);
+    }
+}
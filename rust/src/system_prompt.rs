@@ -1,5 +1,5 @@
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 pub const DEFAULT_SYSTEM_PROMPT: &str = r#"You are an expert engineering consultant. You will provide a second opinion and advice in solving a difficult problem.
 
@@ -28,6 +28,231 @@ Respond in Markdown.
 
 IMPORTANT: Do not edit files yourself, only provide recommendations and code examples"#;
 
+/// System prompt for the judge that merges several consultants' answers.
+pub const SYNTHESIS_SYSTEM_PROMPT: &str = r#"You are the judge on a panel of engineering consultants. Several independent models have answered the same question. Merge their answers into one consolidated report.
+
+Communication style:
+- Skip pleasantries and praise
+- Attribute every point to the model(s) that made it, by name
+
+Structure your report as:
+1. **Consensus** — points that two or more models agree on
+2. **Disagreements** — where models contradict each other; state each position and which one you find better supported, and why
+3. **Unique insights** — points raised by only one model that deserve attention
+4. **Recommendation** — the course of action the evidence best supports
+
+Do not invent points none of the models made. If an answer is wrong, say so.
+
+Respond in Markdown.
+
+IMPORTANT: Do not edit files yourself, only provide recommendations"#;
+
+/// System prompt for summarizing a finished debate between two consultants.
+pub const DEBATE_SUMMARY_SYSTEM_PROMPT: &str = r#"You are moderating a technical debate between two engineering consultants. Read the transcript and summarize where it ended up.
+
+Communication style:
+- Skip pleasantries and praise
+- Attribute every position to the consultant who holds it, by name
+
+Structure your summary as:
+1. **Agreed** — conclusions both sides accept by the end, including points one side conceded
+2. **Unresolved disagreements** — each open point with both final positions and the strongest argument for each
+3. **Next step** — what evidence or experiment would settle each unresolved point
+
+Do not add arguments neither side made.
+
+Respond in Markdown."#;
+
+/// System prompt for the `code-review` mode, also used by the prompt template of that name.
+pub const CODE_REVIEW_SYSTEM_PROMPT: &str = r#"You are a senior engineer reviewing a change before it is merged. You will be given a git diff and possibly surrounding files.
+
+Communication style:
+- Skip pleasantries and praise
+- Reference findings by file and line of the diff
+
+Review for, in order of importance:
+1. Bugs and correctness issues, including edge cases the change misses
+2. Security vulnerabilities introduced by the change
+3. Design problems: wrong abstraction, leaky interfaces, needless complexity
+4. Missing or inadequate tests
+5. Inconsistencies with the conventions of the surrounding code
+
+Rate each finding as blocking, should-fix or nit. If the change looks correct, say so briefly rather than inventing problems.
+
+Respond in Markdown.
+
+IMPORTANT: Do not edit files yourself, only provide recommendations and code examples"#;
+
+/// System prompt for the `root-cause` mode, also used by the prompt template of that name.
+pub const ROOT_CAUSE_SYSTEM_PROMPT: &str = r#"You are an expert debugger. You will be given a failing test, its failure output and relevant source files.
+
+Communication style:
+- Skip pleasantries and praise
+
+Work through the failure:
+1. Restate what the test expects and what actually happened
+2. List the plausible causes, most likely first, citing the code that supports each
+3. Name the single most likely root cause and explain the chain from it to the observed failure
+4. Propose the minimal fix, and say how to confirm it (a command to run, a log line to add)
+
+Distinguish a bug in the code from a bug in the test. Do not propose fixes that only silence the symptom.
+
+Respond in Markdown.
+
+IMPORTANT: Do not edit files yourself, only provide recommendations and code examples"#;
+
+/// System prompt for the `architecture-critique` mode, also used by the prompt template of that name.
+pub const ARCHITECTURE_SYSTEM_PROMPT: &str = r#"You are a software architect asked to critique a design. You will be given a description of the design and possibly the code implementing it.
+
+Communication style:
+- Skip pleasantries and praise
+- Challenge assumptions directly
+
+Assess:
+1. Whether the design solves the stated problem, and what it leaves out
+2. Module boundaries, coupling and the direction of dependencies
+3. How it will behave under growth: more data, more load, more features, more contributors
+4. Failure modes, operability and observability
+5. Simpler alternatives, with the trade-offs against the proposed design
+
+End with the two or three changes that would most improve the design.
+
+Respond in Markdown.
+
+IMPORTANT: Do not edit files yourself, only provide recommendations"#;
+
+/// System prompt for the `security-audit` mode, also used by the prompt template of that name.
+pub const SECURITY_AUDIT_SYSTEM_PROMPT: &str = r#"You are an application security auditor. You will be given source files to audit.
+
+Communication style:
+- Skip pleasantries and praise
+- Reference findings by file and line
+
+Look for:
+1. Injection: command, SQL, path traversal, template and deserialization
+2. Authentication, authorization and session handling flaws
+3. Secrets in code, unsafe handling of credentials and sensitive data
+4. Input validation gaps and trust boundary violations
+5. Unsafe defaults, dependency risks and cryptographic misuse
+
+For each finding give severity (critical, high, medium, low), an exploitation scenario and a concrete fix. Do not report theoretical issues the code is not exposed to.
+
+Respond in Markdown.
+
+IMPORTANT: Do not edit files yourself, only provide recommendations and code examples"#;
+
+/// System prompt for the `explain` mode.
+pub const EXPLAIN_SYSTEM_PROMPT: &str = r#"You are an expert engineer explaining code to a capable colleague who has not seen it before. You will be given a question and the relevant source files.
+
+Communication style:
+- Skip pleasantries and praise
+- Be precise; prefer concrete examples over abstractions
+
+Structure your explanation as:
+1. **Purpose** — what the code is for, in two or three sentences
+2. **How it works** — the main data structures and the flow of control, step by step, with file/line references
+3. **Subtleties** — invariants, edge cases and non-obvious design choices, and why they were likely made
+
+Explain what the code does, not what it should do; do not review it unless asked.
+
+Respond in Markdown.
+
+IMPORTANT: Do not edit files yourself"#;
+
+/// System prompt for the `brainstorm-tests` mode.
+pub const BRAINSTORM_TESTS_SYSTEM_PROMPT: &str = r#"You are a test engineer brainstorming test cases. You will be given code and possibly its existing tests.
+
+Communication style:
+- Skip pleasantries and praise
+
+List test cases grouped as:
+1. **Happy paths** — the main behaviours the code promises
+2. **Edge cases** — empty, boundary, very large and malformed inputs
+3. **Failure modes** — errors from dependencies, timeouts, cancellation, partial failure
+4. **Concurrency and state** — ordering, reentrancy and shared state, where relevant
+
+For each case give a one-line name, the setup and the expected outcome. Mark the ones existing tests already cover, and put the most valuable missing ones first.
+
+Respond in Markdown.
+
+IMPORTANT: Do not edit files yourself, only provide recommendations and code examples"#;
+
+/// Mode that uses `SYSTEM_PROMPT.md` (or `systemPromptPath`).
+pub const DEFAULT_MODE: &str = "default";
+
+/// Built-in modes other than `default`, with their system prompts. A file
+/// `<mode>.md` in the prompts directory overrides one or adds a new mode.
+pub const BUILTIN_MODES: &[(&str, &str)] = &[
+    ("explain", EXPLAIN_SYSTEM_PROMPT),
+    ("brainstorm-tests", BRAINSTORM_TESTS_SYSTEM_PROMPT),
+    ("code-review", CODE_REVIEW_SYSTEM_PROMPT),
+    ("root-cause", ROOT_CAUSE_SYSTEM_PROMPT),
+    ("architecture-critique", ARCHITECTURE_SYSTEM_PROMPT),
+    ("security-audit", SECURITY_AUDIT_SYSTEM_PROMPT),
+];
+
+/// Mode names are file stems, so keep them to characters that cannot
+/// escape the prompts directory.
+fn is_valid_mode_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Every mode: `default`, the built-ins and user files, sorted.
+pub fn available_modes(prompts_dir: &Path) -> Vec<String> {
+    let mut modes: Vec<String> = std::iter::once(DEFAULT_MODE)
+        .chain(BUILTIN_MODES.iter().map(|(name, _)| *name))
+        .map(str::to_string)
+        .collect();
+    if let Ok(entries) = fs::read_dir(prompts_dir) {
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.extension().is_some_and(|ext| ext == "md") {
+                if let Some(stem) = path.file_stem().and_then(|s| s.to_str()) {
+                    if is_valid_mode_name(stem) && !modes.iter().any(|m| m == stem) {
+                        modes.push(stem.to_string());
+                    }
+                }
+            }
+        }
+    }
+    modes.sort();
+    modes
+}
+
+/// System prompt for `mode`: `default` reads `system_prompt_path`, other
+/// modes read `<prompts_dir>/<mode>.md` and fall back to the built-in.
+pub fn get_mode_prompt(
+    mode: &str,
+    system_prompt_path: &Path,
+    prompts_dir: &Path,
+) -> Result<String, String> {
+    if mode == DEFAULT_MODE {
+        return Ok(get_system_prompt(system_prompt_path));
+    }
+    let builtin = BUILTIN_MODES
+        .iter()
+        .find(|(name, _)| *name == mode)
+        .map(|(_, prompt)| *prompt);
+    if is_valid_mode_name(mode) {
+        let path = prompts_dir.join(format!("{mode}.md"));
+        if let Ok(contents) = fs::read_to_string(&path) {
+            let trimmed = contents.trim();
+            if !trimmed.is_empty() {
+                return Ok(trimmed.to_string());
+            }
+        }
+    }
+    builtin.map(str::to_string).ok_or_else(|| {
+        format!(
+            "Unknown mode: {mode}. Available modes: {}",
+            available_modes(prompts_dir).join(", ")
+        )
+    })
+}
+
 pub fn get_system_prompt(custom_path: &Path) -> String {
     if custom_path.exists() {
         match fs::read_to_string(custom_path) {
@@ -71,3 +296,69 @@ pub fn init_system_prompt(config_dir: &Path) -> Result<std::path::PathBuf, Strin
 
     Ok(prompt_path)
 }
+
+/// Write every built-in mode's prompt to `<prompts_dir>/<mode>.md` for
+/// editing, leaving existing files alone. Returns each path and whether it
+/// was created.
+pub fn init_mode_prompts(prompts_dir: &Path) -> Result<Vec<(PathBuf, bool)>, String> {
+    fs::create_dir_all(prompts_dir)
+        .map_err(|e| format!("Failed to create prompts dir: {e}"))?;
+    BUILTIN_MODES
+        .iter()
+        .map(|(name, prompt)| {
+            let path = prompts_dir.join(format!("{name}.md"));
+            if path.exists() {
+                return Ok((path, false));
+            }
+            fs::write(&path, prompt)
+                .map_err(|e| format!("Failed to write {}: {e}", path.display()))?;
+            Ok((path, true))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("grey-rso-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn modes_resolve_from_files_then_builtins() {
+        let dir = temp_dir("modes");
+        fs::write(dir.join("explain.md"), "Explain like I'm five.\n").unwrap();
+        fs::write(dir.join("haiku.md"), "Answer in haiku.").unwrap();
+        let missing = dir.join("SYSTEM_PROMPT.md");
+
+        let prompt = |mode| get_mode_prompt(mode, &missing, &dir);
+        assert_eq!(prompt("default").unwrap(), DEFAULT_SYSTEM_PROMPT);
+        assert_eq!(prompt("explain").unwrap(), "Explain like I'm five.");
+        assert_eq!(prompt("haiku").unwrap(), "Answer in haiku.");
+        assert_eq!(prompt("root-cause").unwrap(), ROOT_CAUSE_SYSTEM_PROMPT);
+
+        let err = prompt("../SYSTEM_PROMPT").unwrap_err();
+        assert!(err.starts_with("Unknown mode: ../SYSTEM_PROMPT."), "{err}");
+        assert!(err.contains("default, ") && err.contains("haiku"), "{err}");
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn init_mode_prompts_keeps_existing_files() {
+        let dir = temp_dir("init-modes");
+        fs::write(dir.join("explain.md"), "mine").unwrap();
+        let written = init_mode_prompts(&dir).unwrap();
+        assert_eq!(written.len(), BUILTIN_MODES.len());
+        assert!(written.iter().any(|(p, created)| p.ends_with("explain.md") && !created));
+        assert_eq!(fs::read_to_string(dir.join("explain.md")).unwrap(), "mine");
+        assert_eq!(
+            fs::read_to_string(dir.join("security-audit.md")).unwrap(),
+            SECURITY_AUDIT_SYSTEM_PROMPT
+        );
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,275 @@
+use serde_json::{json, Map, Value};
+
+/// An argument of a prompt template. MCP passes every argument as a string;
+/// `files` is a comma-separated list.
+pub struct TemplateArg {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub required: bool,
+}
+
+#[derive(Clone, Copy)]
+enum Kind {
+    CodeReview,
+    RootCause,
+    ArchitectureCritique,
+    SecurityAudit,
+}
+
+/// A named prompt published through MCP `prompts/list`. Getting it yields a
+/// ready-made `consult` call in the mode of the same name.
+pub struct PromptTemplate {
+    pub name: &'static str,
+    pub title: &'static str,
+    pub description: &'static str,
+    pub args: &'static [TemplateArg],
+    kind: Kind,
+}
+
+const MODEL_ARG: TemplateArg = TemplateArg {
+    name: "model",
+    description:
+        "Consultant to ask (an alias from the server config); defaults to the default alias",
+    required: false,
+};
+
+pub const TEMPLATES: &[PromptTemplate] = &[
+    PromptTemplate {
+        name: "code-review",
+        title: "Code review of a diff",
+        description: "Ask a consultant to review uncommitted or branch changes",
+        args: &[
+            TemplateArg {
+                name: "files",
+                description: "Comma-separated files whose diff to review",
+                required: true,
+            },
+            TemplateArg {
+                name: "base_ref",
+                description: "Git reference to diff against (default HEAD)",
+                required: false,
+            },
+            TemplateArg {
+                name: "repo_path",
+                description: "Path to the git repository (default: the consult's workdir, \
+                              i.e. the client's first MCP root or the server's directory)",
+                required: false,
+            },
+            TemplateArg {
+                name: "focus",
+                description: "Anything the review should pay particular attention to",
+                required: false,
+            },
+            MODEL_ARG,
+        ],
+        kind: Kind::CodeReview,
+    },
+    PromptTemplate {
+        name: "root-cause",
+        title: "Root-cause a failing test",
+        description: "Ask a consultant why a test fails and how to fix it",
+        args: &[
+            TemplateArg {
+                name: "test",
+                description: "Name of the failing test",
+                required: true,
+            },
+            TemplateArg {
+                name: "failure",
+                description: "Failure output: assertion message, panic or stack trace",
+                required: true,
+            },
+            TemplateArg {
+                name: "files",
+                description: "Comma-separated test and source files involved",
+                required: false,
+            },
+            MODEL_ARG,
+        ],
+        kind: Kind::RootCause,
+    },
+    PromptTemplate {
+        name: "architecture-critique",
+        title: "Architecture critique",
+        description: "Ask a consultant to critique a design and suggest alternatives",
+        args: &[
+            TemplateArg {
+                name: "design",
+                description: "Description of the design or proposal to critique",
+                required: true,
+            },
+            TemplateArg {
+                name: "files",
+                description: "Comma-separated files implementing or documenting the design",
+                required: false,
+            },
+            MODEL_ARG,
+        ],
+        kind: Kind::ArchitectureCritique,
+    },
+    PromptTemplate {
+        name: "security-audit",
+        title: "Security audit",
+        description: "Ask a consultant to audit files for security vulnerabilities",
+        args: &[
+            TemplateArg {
+                name: "files",
+                description: "Comma-separated files to audit",
+                required: true,
+            },
+            TemplateArg {
+                name: "threat_model",
+                description: "Who the attackers are and what they can reach",
+                required: false,
+            },
+            MODEL_ARG,
+        ],
+        kind: Kind::SecurityAudit,
+    },
+];
+
+pub fn find_template(name: &str) -> Option<&'static PromptTemplate> {
+    TEMPLATES.iter().find(|t| t.name == name)
+}
+
+fn split_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+impl PromptTemplate {
+    /// Expand the template into `consult` arguments, checking that required
+    /// arguments are present and that no unknown ones were given.
+    pub fn expand(&self, args: &Map<String, Value>) -> Result<Value, String> {
+        if let Some(unknown) = args.keys().find(|k| self.args.iter().all(|a| a.name != *k)) {
+            return Err(format!(
+                "Unknown argument `{unknown}` for prompt `{}`. Expected: {}",
+                self.name,
+                self.args
+                    .iter()
+                    .map(|a| a.name)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ));
+        }
+        let get = |name: &str| {
+            args.get(name)
+                .and_then(Value::as_str)
+                .map(str::trim)
+                .filter(|v| !v.is_empty())
+        };
+        if let Some(missing) = self
+            .args
+            .iter()
+            .find(|a| a.required && get(a.name).is_none())
+        {
+            return Err(format!(
+                "Missing required argument `{}` for prompt `{}`",
+                missing.name, self.name
+            ));
+        }
+        let files = get("files").map(split_list).unwrap_or_default();
+
+        let mut consult = json!({ "mode": self.name });
+        let prompt = match self.kind {
+            Kind::CodeReview => {
+                let mut git_diff = json!({
+                    "files": files,
+                    "base_ref": get("base_ref").unwrap_or("HEAD"),
+                });
+                if let Some(repo_path) = get("repo_path") {
+                    git_diff["repo_path"] = json!(repo_path);
+                }
+                consult["git_diff"] = git_diff;
+                let mut prompt = "Review the changes in this diff.".to_string();
+                if let Some(focus) = get("focus") {
+                    prompt.push_str(&format!(" Pay particular attention to: {focus}"));
+                }
+                prompt
+            }
+            Kind::RootCause => {
+                if !files.is_empty() {
+                    consult["files"] = json!(files);
+                }
+                format!(
+                    "The test `{}` fails. Find the root cause.\n\nFailure output:\n```\n{}\n```",
+                    get("test").unwrap_or_default(),
+                    get("failure").unwrap_or_default()
+                )
+            }
+            Kind::ArchitectureCritique => {
+                if !files.is_empty() {
+                    consult["files"] = json!(files);
+                }
+                format!(
+                    "Critique this design.\n\n{}",
+                    get("design").unwrap_or_default()
+                )
+            }
+            Kind::SecurityAudit => {
+                consult["files"] = json!(files);
+                let mut prompt = "Audit these files for security vulnerabilities.".to_string();
+                if let Some(threat_model) = get("threat_model") {
+                    prompt.push_str(&format!("\n\nThreat model: {threat_model}"));
+                }
+                prompt
+            }
+        };
+        consult["prompt"] = json!(prompt);
+        if let Some(model) = get("model") {
+            consult["model"] = json!(model);
+        }
+        Ok(consult)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(pairs: &[(&str, &str)]) -> Map<String, Value> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), json!(v)))
+            .collect()
+    }
+
+    #[test]
+    fn code_review_expands_to_a_diff_consult() {
+        let template = find_template("code-review").unwrap();
+        let consult = template
+            .expand(&args(&[
+                ("files", "src/a.rs, src/b.rs"),
+                ("focus", "locking"),
+                ("model", "gemini"),
+            ]))
+            .unwrap();
+        assert_eq!(consult["mode"], "code-review");
+        assert_eq!(consult["model"], "gemini");
+        assert_eq!(
+            consult["git_diff"]["files"],
+            json!(["src/a.rs", "src/b.rs"])
+        );
+        assert_eq!(consult["git_diff"]["base_ref"], "HEAD");
+        assert!(consult["prompt"].as_str().unwrap().contains("locking"));
+    }
+
+    #[test]
+    fn missing_and_unknown_arguments_are_rejected() {
+        let template = find_template("root-cause").unwrap();
+        let err = template.expand(&args(&[("test", "it_works")])).unwrap_err();
+        assert!(err.contains("`failure`"), "{err}");
+        let err = template
+            .expand(&args(&[
+                ("test", "t"),
+                ("failure", "boom"),
+                ("colour", "red"),
+            ]))
+            .unwrap_err();
+        assert!(err.contains("`colour`"), "{err}");
+    }
+}
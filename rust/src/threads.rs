@@ -0,0 +1,123 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+/// Threads kept in memory; the least recently used is forgotten beyond this.
+const MAX_THREADS: usize = 256;
+
+/// One question and its answer within a thread.
+#[derive(Debug, Clone)]
+pub struct Turn {
+    pub prompt: String,
+    pub answer: String,
+}
+
+/// A conversation with one consultant that later `consult` calls can
+/// continue.
+#[derive(Debug, Clone)]
+pub struct Thread {
+    pub alias: String,
+    /// The provider's own session id, when it reported one and can resume it.
+    pub session_id: Option<String>,
+    pub turns: Vec<Turn>,
+    /// Context files from every turn, replayed for providers without a
+    /// native session.
+    pub files: Vec<String>,
+    /// Most recent git diff given on the thread.
+    pub git_diff: Option<String>,
+    /// Directory the consultant runs in; follow-ups stay there.
+    pub workdir: Option<PathBuf>,
+}
+
+/// Threads by id. Ids are only meaningful to the server process that
+/// issued them.
+pub struct ThreadStore {
+    /// Each thread with the tick it was last read or saved at.
+    threads: Mutex<HashMap<String, (Thread, u64)>>,
+    next_id: AtomicU64,
+    tick: AtomicU64,
+}
+
+impl ThreadStore {
+    pub fn new() -> Self {
+        ThreadStore {
+            threads: Mutex::new(HashMap::new()),
+            next_id: AtomicU64::new(1),
+            tick: AtomicU64::new(0),
+        }
+    }
+
+    /// The thread saved under `id`, which counts as a use of it.
+    pub fn get(&self, id: &str) -> Option<Thread> {
+        let mut threads = self.threads.lock().unwrap();
+        let (thread, used) = threads.get_mut(id)?;
+        *used = self.tick.fetch_add(1, Ordering::Relaxed);
+        Some(thread.clone())
+    }
+
+    /// Store `thread` under `id`, or under a new id when `id` is `None`, and
+    /// return the id.
+    pub fn save(&self, id: Option<&str>, thread: Thread) -> String {
+        let id = match id {
+            Some(id) => id.to_string(),
+            None => format!("thread-{}", self.next_id.fetch_add(1, Ordering::Relaxed)),
+        };
+        let mut threads = self.threads.lock().unwrap();
+        let tick = self.tick.fetch_add(1, Ordering::Relaxed);
+        threads.insert(id.clone(), (thread, tick));
+        if threads.len() > MAX_THREADS {
+            let oldest = threads
+                .iter()
+                .min_by_key(|(_, (_, used))| *used)
+                .map(|(id, _)| id.clone());
+            if let Some(oldest) = oldest {
+                threads.remove(&oldest);
+            }
+        }
+        id
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn thread(alias: &str) -> Thread {
+        Thread {
+            alias: alias.into(),
+            session_id: None,
+            turns: vec![],
+            files: vec![],
+            git_diff: None,
+            workdir: None,
+        }
+    }
+
+    #[test]
+    fn save_assigns_ids_and_updates_in_place() {
+        let store = ThreadStore::new();
+        let id = store.save(None, thread("claude"));
+        assert_ne!(store.save(None, thread("codex")), id);
+
+        let mut updated = store.get(&id).unwrap();
+        updated.session_id = Some("abc".into());
+        assert_eq!(store.save(Some(&id), updated), id);
+        assert_eq!(store.get(&id).unwrap().session_id.as_deref(), Some("abc"));
+        assert!(store.get("thread-999").is_none());
+    }
+
+    #[test]
+    fn least_recently_used_thread_is_evicted() {
+        let store = ThreadStore::new();
+        let first = store.save(None, thread("claude"));
+        let second = store.save(None, thread("claude"));
+        for _ in 2..MAX_THREADS {
+            store.save(None, thread("claude"));
+        }
+        assert!(store.get(&first).is_some());
+        store.save(None, thread("claude"));
+        assert!(store.get(&second).is_none());
+        assert!(store.get(&first).is_some());
+    }
+}